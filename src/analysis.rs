@@ -0,0 +1,324 @@
+use crate::bitmap::Bitmap2D;
+use crate::piece::{Placement, PaddedPieces, PIECE_ORDER};
+use crate::solver::{increment, Solver};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// How hard a puzzle is, from the solver's point of view and from the
+/// rules of thumb a person would use.
+pub struct Rating {
+    /// Pieces placed before the first solution, in the order `fill_board`
+    /// tries them.
+    pub nodes: u64,
+    pub solutions: usize,
+    /// Whether both searches ended before the timeout. When they did not,
+    /// `nodes` and `solutions` only count what was searched in time.
+    pub complete: bool,
+    /// Placements that fit at the first empty cell.
+    pub branching: usize,
+    /// Pieces that can be placed one after the other because some empty
+    /// cell is only covered by one placement that fits.
+    pub forced: usize,
+    /// `log2(1 + nodes) + log2(1 + branching) - log2(solutions)`, scaled
+    /// down by the share of forced pieces. Only meant to rank puzzles.
+    pub score: f64,
+}
+
+/// Placements of the pieces left that fit on `board`, with their piece
+/// index.
+fn fitting<'a>(tables: &'a PaddedPieces, board: &Bitmap2D, remaining: &[u32; 7])
+               -> Vec<(usize, &'a Placement)> {
+    tables.iter().enumerate()
+        .filter(|(piece_id, _)| remaining[*piece_id] > 0)
+        .flat_map(|(piece_id, table)| table.values().flatten().map(move |p| (piece_id, p)))
+        .filter(|(_, placement)| !board.intersects(&placement.bitmap))
+        .collect()
+}
+
+fn first_empty(board: &Bitmap2D) -> Option<(usize, usize)> {
+    let mut position = Some((0, 0));
+    while let Some(cell) = position {
+        if !board.get(cell).unwrap_or(true) { return Some(cell) }
+        position = increment(board.shape, cell);
+    }
+    None
+}
+
+/// The first empty cell, in row-major order, that a single fitting
+/// placement covers, with that placement and its piece index. None when
+/// every cell has a choice or some cell cannot be covered at all.
+fn forced_placement<'a>(tables: &'a PaddedPieces, board: &Bitmap2D, remaining: &[u32; 7])
+                        -> Option<((usize, usize), usize, &'a Placement)> {
+    let candidates = fitting(tables, board, remaining);
+    let empty = (0..board.shape.0)
+        .flat_map(|row| (0..board.shape.1).map(move |col| (row, col)))
+        .filter(|&cell| !board.get(cell).unwrap_or(true));
+    for cell in empty {
+        let mut covering = candidates.iter()
+            .filter(|(_, placement)| placement.bitmap.get(cell).unwrap_or(false));
+        match (covering.next(), covering.next()) {
+            (Some(&(piece_id, placement)), None) => return Some((cell, piece_id, placement)),
+            // Nothing can cover this cell: the puzzle is stuck.
+            (None, _) => return None,
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Places pieces for as long as some empty cell has a single fitting
+/// placement covering it.
+pub fn forced_placements(tables: &PaddedPieces, board: &Bitmap2D, remaining: [u32; 7]) -> usize {
+    let (mut board, mut remaining) = (Bitmap2D { shape: board.shape, data: board.data.clone() },
+                                      remaining);
+    let mut forced = 0;
+    while let Some((_, piece_id, placement)) = forced_placement(tables, &board, &remaining) {
+        board = board.or(&placement.bitmap);
+        remaining[piece_id] -= 1;
+        forced += 1;
+    }
+    forced
+}
+
+/// Why a hint is safe to play.
+#[derive(Debug, PartialEq)]
+pub enum HintKind {
+    /// The only fitting placement that covers this cell.
+    Forced((usize, usize)),
+    /// In every solution, or every solution found before the timeout.
+    Common,
+    /// In some solution but not in all of them.
+    Possible,
+}
+
+pub struct Hint<'a> {
+    pub placement: &'a Placement,
+    pub kind: HintKind,
+    /// Number of solutions, when the search went through all of them.
+    pub solutions: Option<usize>,
+}
+
+/// One placement to play next that keeps the puzzle solvable: a forced one
+/// if any, else the top-left one common to all solutions, else the one
+/// covering the first empty cell in the first solution. None when the
+/// puzzle has no solution, or `solver` timed out before finding one.
+pub fn hint<'a>(tables: &'a PaddedPieces, solver: &mut Solver<'a>, board: &Bitmap2D,
+                remaining: [u32; 7]) -> Option<Hint<'a>> {
+    // Every solution has the forced placement, so one solution after it is
+    // enough to know the puzzle is solvable.
+    if let Some((cell, piece_id, placement)) = forced_placement(tables, board, &remaining) {
+        let mut rest = remaining;
+        rest[piece_id] -= 1;
+        solver.first(&board.or(&placement.bitmap), rest)?;
+        return Some(Hint { placement, kind: HintKind::Forced(cell), solutions: None });
+    }
+
+    let mut first: Option<Vec<&Placement>> = None;
+    let mut common: Vec<&Placement> = Vec::new();
+    let mut count = 0;
+    solver.for_each(board, remaining, |sol| {
+        count += 1;
+        match first {
+            None => {
+                first = Some(sol.to_vec());
+                common = sol.to_vec();
+            },
+            Some(_) => common.retain(|&p| sol.iter().any(|&q| std::ptr::eq(p, q))),
+        }
+        // Nothing left to narrow down.
+        if common.is_empty() { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+    });
+    let first = first?;
+    let solutions = (!solver.timed_out && !common.is_empty()).then_some(count);
+    if let Some(&placement) = common.iter().min_by_key(|p| p.origin) {
+        return Some(Hint { placement, kind: HintKind::Common, solutions });
+    }
+    let cell = first_empty(board)?;
+    let placement = first.into_iter().find(|p| p.bitmap.get(cell).unwrap_or(false))?;
+    Some(Hint { placement, kind: HintKind::Possible, solutions })
+}
+
+/// Rates the puzzle of filling `board` with `remaining`, searching for at
+/// most `timeout` in total.
+pub fn rate(tables: &PaddedPieces, board: &Bitmap2D, remaining: [u32; 7],
+            timeout: Option<Duration>) -> Rating {
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let mut solver = Solver::new(tables).with_timeout(timeout);
+    solver.first(board, remaining);
+    let nodes = solver.nodes;
+    let left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+    let mut counter = Solver::new(tables).with_timeout(left);
+    let solutions = counter.count(board, remaining);
+    let branching = first_empty(board).map_or(0, |cell| {
+        fitting(tables, board, &remaining).iter().filter(|(_, p)| p.origin == cell).count()
+    });
+    let forced = forced_placements(tables, board, remaining);
+
+    let pieces = remaining.iter().sum::<u32>().max(1) as f64;
+    let raw = (1.0 + nodes as f64).log2() + (1.0 + branching as f64).log2()
+        - (solutions.max(1) as f64).log2();
+    let score = (raw*(1.0 - forced as f64 / pieces)).max(0.0);
+    let complete = !solver.timed_out && !counter.timed_out;
+    Rating { nodes, solutions, complete, branching, forced, score }
+}
+
+/// What every solution has in common.
+pub struct Backbone<'a> {
+    pub solutions: usize,
+    /// Placements found in every solution, in row-major order of origins.
+    pub placements: Vec<&'a Placement>,
+    /// Piece types covering each cell in some solution, indexed like
+    /// `PIECE_ORDER`, cells in row-major order. Cells filled before solving
+    /// have none.
+    pub cell_types: Vec<[bool; 7]>,
+}
+
+/// Goes through every solution `solver` finds before its timeout. None when
+/// there is no solution.
+pub fn backbone<'a>(solver: &mut Solver<'a>, board: &Bitmap2D, remaining: [u32; 7])
+                    -> Option<Backbone<'a>> {
+    let cols = board.shape.1;
+    let mut cell_types = vec![[false; 7]; board.shape.0*cols];
+    let mut common: Option<Vec<&Placement>> = None;
+    let mut solutions = 0;
+    solver.for_each(board, remaining, |sol| {
+        solutions += 1;
+        for placement in sol {
+            let piece_id = PIECE_ORDER.iter().position(|&c| c == placement.piece).unwrap();
+            for (row, col) in placement.bitmap.ones() {
+                cell_types[row*cols + col][piece_id] = true;
+            }
+        }
+        match &mut common {
+            None => common = Some(sol.to_vec()),
+            Some(common) => common.retain(|&p| sol.iter().any(|&q| std::ptr::eq(p, q))),
+        }
+        ControlFlow::Continue(())
+    });
+    let mut placements = common?;
+    placements.sort_by_key(|p| p.origin);
+    Some(Backbone { solutions, placements, cell_types })
+}
+
+/// Number of solutions in which each piece type covers each cell, indexed
+/// like `PIECE_ORDER`, cells in row-major order.
+pub struct Heatmap {
+    pub solutions: u64,
+    pub counts: Vec<[u64; 7]>,
+}
+
+/// Counts the pieces covering each cell over the solutions `solver` finds
+/// before its timeout, without keeping the solutions.
+pub fn heatmap(solver: &mut Solver, board: &Bitmap2D, remaining: [u32; 7]) -> Heatmap {
+    let cols = board.shape.1;
+    let mut heatmap = Heatmap { solutions: 0, counts: vec![[0; 7]; board.shape.0*cols] };
+    solver.for_each(board, remaining, |sol| {
+        heatmap.solutions += 1;
+        for placement in sol {
+            let piece_id = PIECE_ORDER.iter().position(|&c| c == placement.piece).unwrap();
+            for (row, col) in placement.bitmap.ones() {
+                heatmap.counts[row*cols + col][piece_id] += 1;
+            }
+        }
+        ControlFlow::Continue(())
+    });
+    heatmap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::{get_padded_pieces, parse_pieces, PIECE_ORDER};
+
+    #[test]
+    fn ratings() {
+        // Two I pieces fill a 2x4 board in one way, both forced.
+        let board = Bitmap2D::zeros((2, 4));
+        let tables = get_padded_pieces(board.shape);
+        let rating = rate(&tables, &board, parse_pieces("II").unwrap(), None);
+        assert_eq!((rating.nodes, rating.solutions, rating.branching, rating.forced),
+                   (2, 1, 1, 2));
+        assert_eq!(rating.score, 0.0);
+
+        let board = Bitmap2D::zeros((5, 8));
+        let tables = get_padded_pieces(board.shape);
+        let rating = rate(&tables, &board, parse_pieces("IIIIJJLLSZ").unwrap(), None);
+        assert!(rating.complete && rating.solutions > 1);
+        assert!(rating.nodes > 10 && rating.score > 0.0);
+
+        // The timeout also bounds the search for a first solution.
+        let board = Bitmap2D::zeros((10, 10));
+        let tables = get_padded_pieces(board.shape);
+        let pieces = parse_pieces("IIIIIIIIIIJJJJJLLLLLTTTTT").unwrap();
+        let rating = rate(&tables, &board, pieces, Some(Duration::ZERO));
+        assert!(!rating.complete && rating.solutions == 0);
+    }
+
+    #[test]
+    fn hints() {
+        // The top-left corner of a 2x4 board can only take a flat I.
+        let board = Bitmap2D::zeros((2, 4));
+        let tables = get_padded_pieces(board.shape);
+        let hint = hint(&tables, &mut Solver::new(&tables), &board,
+                        parse_pieces("II").unwrap()).unwrap();
+        assert_eq!((hint.kind, hint.solutions), (HintKind::Forced((0, 0)), None));
+        assert_eq!((hint.placement.piece, hint.placement.origin), ('I', (0, 0)));
+
+        // Two Is and two Os fill a 4x4 board in several ways with no
+        // placement in common, but the hint still belongs to a solution.
+        let board = Bitmap2D::zeros((4, 4));
+        let tables = get_padded_pieces(board.shape);
+        let remaining = parse_pieces("IIOO").unwrap();
+        let hint = super::hint(&tables, &mut Solver::new(&tables), &board, remaining).unwrap();
+        assert_eq!((hint.kind, hint.solutions), (HintKind::Possible, None));
+        let mut rest = remaining;
+        rest[PIECE_ORDER.iter().position(|&c| c == hint.placement.piece).unwrap()] -= 1;
+        let after = board.or(&hint.placement.bitmap);
+        assert!(Solver::new(&tables).first(&after, rest).is_some());
+
+        let mut solver = Solver::new(&tables);
+        assert!(super::hint(&tables, &mut solver, &board, parse_pieces("TTTS").unwrap()).is_none());
+
+        // Both IILLTT tilings of 4x6 share an L in the top-right corner.
+        let board = Bitmap2D::zeros((4, 6));
+        let tables = get_padded_pieces(board.shape);
+        let hint = super::hint(&tables, &mut Solver::new(&tables), &board,
+                               parse_pieces("IILLTT").unwrap()).unwrap();
+        assert_eq!((hint.kind, hint.solutions), (HintKind::Common, Some(2)));
+        assert_eq!((hint.placement.piece, hint.placement.origin), ('L', (0, 4)));
+    }
+
+    #[test]
+    fn backbones() {
+        // IIOO on 4x4: two Is on one side of the Os, or one on each side,
+        // with rows and columns swapped.
+        let board = Bitmap2D::zeros((4, 4));
+        let tables = get_padded_pieces(board.shape);
+        let backbone = backbone(&mut Solver::new(&tables), &board,
+                                parse_pieces("IIOO").unwrap()).unwrap();
+        assert_eq!(backbone.solutions, 6);
+        assert!(backbone.placements.is_empty());
+        let types = |cell: usize| backbone.cell_types[cell].iter().filter(|&&t| t).count();
+        assert!((0..16).all(|cell| types(cell) == 2));
+
+        // A single solution is its own backbone.
+        let board = Bitmap2D::zeros((2, 4));
+        let tables = get_padded_pieces(board.shape);
+        let backbone = super::backbone(&mut Solver::new(&tables), &board,
+                                       parse_pieces("II").unwrap()).unwrap();
+        assert_eq!((backbone.solutions, backbone.placements.len()), (1, 2));
+        assert!(backbone.cell_types.iter().all(|types| *types == [false, true, false, false,
+                                                                  false, false, false]));
+    }
+
+    #[test]
+    fn heatmaps() {
+        // Out of the six IIOO tilings of 4x4, corners get an I in four.
+        let board = Bitmap2D::zeros((4, 4));
+        let tables = get_padded_pieces(board.shape);
+        let heatmap = heatmap(&mut Solver::new(&tables), &board, parse_pieces("IIOO").unwrap());
+        assert_eq!(heatmap.solutions, 6);
+        assert_eq!(heatmap.counts[0], [0, 4, 0, 0, 0, 0, 2]);
+        assert!(heatmap.counts.iter().all(|counts| counts.iter().sum::<u64>() == 6));
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::iter::Iterator;
 
 #[derive(Debug)]
@@ -8,12 +9,12 @@ pub struct Bitmap1D {
 
 impl Bitmap1D {
     pub fn zeros(len: usize) -> Self {
-        let datasize = (len + 63) / 64;
+        let datasize = len.div_ceil(64);
         Self { len, data: vec![0; datasize] }
     }
 
     pub fn mask_oob(&mut self) {
-        let last_chunk_id = (self.len + 63) / 64;
+        let last_chunk_id = self.len.div_ceil(64);
         if let Some(chunk) = self.data.get_mut(last_chunk_id-1) {
             let bitmask: u64 = !(!0 >> (self.len%64));
             *chunk &= bitmask;
@@ -71,21 +72,23 @@ impl Bitmap1D {
     //    return Some((chunk >> (coord % 64)) % 2 == 1);
     //}
 
-    pub fn to_string(&self) -> String {
-        let mut out = String::new();
+}
+
+impl fmt::Display for Bitmap1D {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut remaining_bits = self.len;
         for item in &self.data {
             let mut binary = &format!("{item:064b}")[..];
             if remaining_bits < 64 {
                 binary = &binary[..remaining_bits];
             }
-            out.push_str(binary);
+            f.write_str(binary)?;
             remaining_bits = match remaining_bits.checked_sub(64) {
                 Some(i) => i,
                 None => break
             };
         }
-        out
+        Ok(())
     }
 }
 
@@ -96,7 +99,7 @@ pub struct Bitmap2D {
 
 impl Bitmap2D {
     pub fn zeros(shape: (usize, usize)) -> Self {
-        let datasize = (shape.0*shape.1 + 63) / 64;
+        let datasize = (shape.0*shape.1).div_ceil(64);
         Self { shape, data: vec![0; datasize] }
     }
 
@@ -108,23 +111,17 @@ impl Bitmap2D {
         for line_id in 0..self.shape.0 {
             out.push(line.sub_bitmap(line_id*self.shape.1, Some((line_id+1)*self.shape.1 - 1)));
         }
-        return out
-    }
-
-    pub fn to_string(&self) -> String {
-        let lines = self.get_lines();
-        let lines_str: Vec<_> = lines.into_iter().map(|l| l.to_string()).collect();
-        return lines_str.join("\n");
+        out
     }
 
     pub fn stack(mut lines: Vec<Bitmap1D>) -> Self {
-        let first_line = match lines.get(0) {
+        let first_line = match lines.first() {
             Some(l) => l,
             None => return Bitmap2D { shape: (0,0), data: Vec::new() }
         };
         let line_len = first_line.len;
         let new_len = line_len*lines.len();
-        let mut new_arr = vec![0; (new_len + 63)/64];
+        let mut new_arr = vec![0; new_len.div_ceil(64)];
         for (i, line) in lines.iter_mut().enumerate() {
             line.mask_oob();
             let padded = line.pad(i*line_len, new_len - (i+1)*line_len);
@@ -152,7 +149,24 @@ impl Bitmap2D {
     pub fn get(&self, coord: (usize, usize)) -> Option<bool> {
         let idx = coord.0*self.shape.1 + coord.1;
         let chunk = self.data.get(idx / 64)?;
-        return Some((chunk >> (63 - (idx % 64))) % 2 == 1);
+        Some((chunk >> (63 - (idx % 64))) % 2 == 1)
+    }
+
+    pub fn set(&mut self, coord: (usize, usize)) {
+        let idx = coord.0*self.shape.1 + coord.1;
+        self.data[idx / 64] |= 1 << (63 - (idx % 64));
+    }
+
+    pub fn ones(&self) -> Vec<(usize, usize)> {
+        let mut out = Vec::new();
+        for row in 0..self.shape.0 {
+            for col in 0..self.shape.1 {
+                if self.get((row, col)).unwrap_or(false) {
+                    out.push((row, col));
+                }
+            }
+        }
+        out
     }
 
     pub fn intersects(&self, other: &Self) -> bool {
@@ -185,6 +199,14 @@ impl Bitmap2D {
 
 }
 
+impl fmt::Display for Bitmap2D {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = self.get_lines();
+        let lines_str: Vec<_> = lines.into_iter().map(|l| l.to_string()).collect();
+        f.write_str(&lines_str.join("\n"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -272,6 +294,22 @@ mod tests {
         assert_eq!(j_piece.get((3,3)), Some(false));
     }
 
+    #[test]
+    fn ones() {
+        let j_piece = Bitmap2D { shape: (3, 2), data: vec![0b010111 << 58] };
+        assert_eq!(j_piece.ones(), vec![(0,1), (1,1), (2,0), (2,1)]);
+        let padded = j_piece.pad_to((7, 10), (2, 3));
+        assert_eq!(padded.ones(), vec![(2,4), (3,4), (4,3), (4,4)]);
+    }
+
+    #[test]
+    fn set() {
+        let mut bm = Bitmap2D::zeros((3, 30));
+        bm.set((2, 10));
+        bm.set((0, 1));
+        assert_eq!(bm.ones(), vec![(0, 1), (2, 10)]);
+    }
+
     #[test]
     fn intersection() {
         let j_piece = Bitmap2D { shape: (3, 2), data: vec![0b010111 << 58] };
@@ -321,7 +359,7 @@ mod tests {
         //assert_eq!(padded1.to_string(), "0100\n0100\n1100\n0000");
         let padded2 = j_piece.pad_to((4, 4), (1,2));
         //assert_eq!(padded1.to_string(), "0000\n0001\n0001\n0011");
-        let added = Bitmap2D::print_all(vec![padded1, padded2].iter());
+        let added = Bitmap2D::print_all([padded1, padded2].iter());
         assert_eq!(added, "0100\n0102\n1102\n0022");
     }
     
@@ -0,0 +1,1973 @@
+use crate::analysis::{self, Backbone, HintKind, Rating};
+use crate::bitmap::Bitmap2D;
+use crate::error::TalosError;
+use crate::fumen;
+use crate::generate;
+use crate::gravity::{self, DropMode};
+use crate::json::{self, Json};
+use crate::layout;
+use crate::multisets;
+use crate::piece::*;
+use crate::plan::{minus, Door, Planner};
+use crate::puzzle::{Constraint, Puzzle};
+use crate::render::{self, ColorBy};
+use crate::rng::Rng;
+use crate::segment;
+use crate::solver::*;
+use crate::term::{self, ColorSupport, Palette};
+use crate::tetris::{self, PerfectClear};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+/// Boards above this many cells make the placement tables too large.
+const MAX_CELLS: usize = 4096;
+
+struct OptSpec {
+    name: &'static str,
+    /// Placeholder shown in help for options taking a value, `None` for flags.
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+const fn flag(name: &'static str, help: &'static str) -> OptSpec {
+    OptSpec { name, value: None, help }
+}
+
+const fn opt(name: &'static str, value: &'static str, help: &'static str) -> OptSpec {
+    OptSpec { name, value: Some(value), help }
+}
+
+struct CommandSpec {
+    name: &'static str,
+    summary: &'static str,
+    usage: &'static [&'static str],
+    options: &'static [&'static [OptSpec]],
+}
+
+const PUZZLE_OPTIONS: &[OptSpec] = &[
+    opt("size", "WxH", "Board size, same order as the positional W H"),
+    opt("pieces", "PIECES", "Pieces to place, e.g. IIIIJJLLSZ"),
+    opt("file", "FILE", "Read the puzzle from a puzzle file ('-' for stdin)"),
+];
+
+const TIMEOUT_OPTION: OptSpec =
+    opt("timeout", "SECS", "Give up after SECS seconds (exit code 3)");
+
+const GRAVITY_OPTION: OptSpec =
+    opt("gravity", "MODE", "Only keep solutions whose pieces can be dropped in: straight, shift or srs");
+
+const QUEUE_OPTIONS: &[OptSpec] = &[
+    opt("queue", "PIECES", "Only keep solutions whose pieces can be dropped in this order \
+                            (--gravity defaults to shift)"),
+    flag("hold", "Allow holding one piece of --queue"),
+];
+
+const RENDER_OPTIONS: &[OptSpec] = &[
+    opt("format", "FORMAT", "Output format: text, box, instructions, json, ndjson or fumen \
+                             (default text)"),
+    opt("color", "MODE", "Colors: auto, never, 16, 256 or truecolor (default auto)"),
+    opt("palette", "NAME", "Palette: default, talos or colorblind"),
+    opt("color-by", "MODE", "neighbour (distinct neighbours) or piece (one color per type)"),
+];
+
+const LAYOUT_OPTIONS: &[OptSpec] = &[
+    opt("width", "COLS", "Width used to lay out several solutions (default $COLUMNS or 80)"),
+    opt("page", "N", "Page of solutions to show with --all (default 1)"),
+    opt("page-size", "N", "Solutions per page with --all (default all)"),
+];
+
+const SOLVE: CommandSpec = CommandSpec {
+    name: "solve",
+    summary: "Find a tiling of the board with the given pieces",
+    usage: &["talos solve W H PIECES [OPTIONS]",
+             "talos solve --size WxH --pieces PIECES [OPTIONS]",
+             "talos solve --file FILE [OPTIONS]"],
+    options: &[PUZZLE_OPTIONS,
+               &[flag("all", "Show every solution instead of the first one"),
+                 flag("find-all", "Same as --all"),
+                 TIMEOUT_OPTION,
+                 GRAVITY_OPTION,
+                 opt("save", "FILE", "Write the puzzle with its first solution to FILE")],
+               QUEUE_OPTIONS, RENDER_OPTIONS, LAYOUT_OPTIONS],
+};
+
+const COUNT: CommandSpec = CommandSpec {
+    name: "count",
+    summary: "Count the tilings of the board with the given pieces",
+    usage: &["talos count W H PIECES",
+             "talos count --size WxH --pieces PIECES",
+             "talos count --file FILE"],
+    options: &[PUZZLE_OPTIONS, &[TIMEOUT_OPTION, GRAVITY_OPTION], QUEUE_OPTIONS],
+};
+
+const VERIFY: CommandSpec = CommandSpec {
+    name: "verify",
+    summary: "Check a solution grid of piece letters, rows separated by '/' or newlines",
+    usage: &["talos verify GRID [--pieces PIECES] [--size WxH]",
+             "talos verify --grid-file FILE [--pieces PIECES] [--size WxH]"],
+    options: &[&[opt("pieces", "PIECES", "Pieces the grid must be made of"),
+                 opt("size", "WxH", "Size the grid must have"),
+                 opt("grid-file", "FILE", "Read the grid from FILE ('-' for stdin)")]],
+};
+
+const GENERATE: CommandSpec = CommandSpec {
+    name: "generate",
+    summary: "Build a random tiling of a board and print it as a puzzle",
+    usage: &["talos generate --size WxH [--seed N] [OPTIONS]",
+             "talos generate --size WxH --unique [--min-nodes N] [--max-nodes N] [OPTIONS]"],
+    options: &[&[opt("size", "WxH", "Board size"),
+                 opt("pieces", "N", "Number of pieces, which must cover the board"),
+                 opt("seed", "N", "Seed for the random generator (default: clock)"),
+                 flag("unique", "Retry until the pieces have a single solution up to symmetry"),
+                 opt("min-nodes", "N", "Retry until the solver needs at least N nodes"),
+                 opt("max-nodes", "N", "Retry until the solver needs at most N nodes"),
+                 opt("attempts", "N", "Tilings to try before giving up (default 1000)"),
+                 TIMEOUT_OPTION],
+               RENDER_OPTIONS],
+};
+
+const BENCH: CommandSpec = CommandSpec {
+    name: "bench",
+    summary: "Time the solver on a puzzle or on a built-in suite",
+    usage: &["talos bench [--size WxH --pieces PIECES] [--runs N] [--all]"],
+    options: &[PUZZLE_OPTIONS,
+               &[opt("runs", "N", "Runs per puzzle (default 3)"),
+                 flag("all", "Time the enumeration of every solution")]],
+};
+
+const BATCH: CommandSpec = CommandSpec {
+    name: "batch",
+    summary: "Solve one puzzle per line (W H PIECES [--all] [--timeout SECS]) from a file or stdin",
+    usage: &["talos batch [FILE] [OPTIONS]"],
+    options: &[&[flag("all", "Count every solution of each puzzle"), TIMEOUT_OPTION],
+               RENDER_OPTIONS],
+};
+
+/// Options allowed on each line of a batch file.
+const BATCH_LINE: CommandSpec = CommandSpec {
+    name: "batch",
+    summary: "",
+    usage: &[],
+    options: &[PUZZLE_OPTIONS,
+               &[flag("all", "Count every solution of this puzzle"), TIMEOUT_OPTION]],
+};
+
+const PLAN: CommandSpec = CommandSpec {
+    name: "plan",
+    summary: "Choose which doors to open with the pieces collected so far",
+    usage: &["talos plan --inventory PIECES DOOR... [OPTIONS]",
+             "where DOOR is WxH:PIECES, or WxH when any pieces will do"],
+    options: &[&[opt("inventory", "PIECES", "Pieces available for all the doors")],
+               RENDER_OPTIONS],
+};
+
+const PC: CommandSpec = CommandSpec {
+    name: "pc",
+    summary: "Find a perfect clear on a 10-wide Tetris stack with a piece queue",
+    usage: &["talos pc --queue PIECES [--stack ROWS] [--height N] [--hold] [OPTIONS]",
+             "talos pc --fumen CODE [--queue PIECES] [OPTIONS]"],
+    options: &[&[opt("queue", "PIECES", "Pieces in the order they come"),
+                 opt("stack", "ROWS", "Existing stack, '#' for filled cells, rows top to bottom \
+                                       separated by '/'"),
+                 opt("fumen", "CODE", "Read the stack, and the queue unless --queue is given, \
+                                       from the first page of a v115 fumen"),
+                 opt("height", "N", "Rows the stack may reach (default 4)"),
+                 flag("hold", "Allow holding a piece"),
+                 opt("held", "PIECE", "Piece already in hold, implies --hold"),
+                 flag("srs", "Allow rotations with SRS wall kicks, not just shifts"),
+                 TIMEOUT_OPTION,
+                 opt("format", "FORMAT", "Output format: text, json or fumen (default text)")]],
+};
+
+const RATE: CommandSpec = CommandSpec {
+    name: "rate",
+    summary: "Rate how hard a puzzle is",
+    usage: &["talos rate W H PIECES [OPTIONS]",
+             "talos rate --size WxH --pieces PIECES [OPTIONS]",
+             "talos rate --file FILE [OPTIONS]"],
+    options: &[PUZZLE_OPTIONS,
+               &[opt("timeout", "SECS", "Stop counting solutions after SECS seconds per puzzle"),
+                 opt("format", "FORMAT", "Output format: text or json (default text)")]],
+};
+
+const MULTISETS: CommandSpec = CommandSpec {
+    name: "multisets",
+    summary: "List every piece multiset that tiles a board, with its number of tilings",
+    usage: &["talos multisets W H [OPTIONS]",
+             "talos multisets --size WxH [OPTIONS]"],
+    options: &[&[opt("size", "WxH", "Board size"),
+                 TIMEOUT_OPTION,
+                 opt("format", "FORMAT", "Output format: text or json (default text)")]],
+};
+
+const FIT: CommandSpec = CommandSpec {
+    name: "fit",
+    summary: "Find every rectangle the given pieces can tile",
+    usage: &["talos fit PIECES [OPTIONS]"],
+    options: &[&[flag("all-orientations", "List W x H and H x W separately (default: W <= H)"),
+                 opt("timeout", "SECS", "Give up on a rectangle after SECS seconds")],
+               RENDER_OPTIONS],
+};
+
+const HINT: CommandSpec = CommandSpec {
+    name: "hint",
+    summary: "Reveal one piece of a solution, given the pieces already placed",
+    usage: &["talos hint W H PIECES [OPTIONS]",
+             "talos hint --size WxH --pieces PIECES [OPTIONS]",
+             "talos hint --file FILE [--apply] [OPTIONS]"],
+    options: &[PUZZLE_OPTIONS,
+               &[flag("apply", "Add the hint to --file as a 'place' line, so that the next \
+                                call reveals the next piece"),
+                 opt("timeout", "SECS", "Stop looking at solutions after SECS seconds")],
+               RENDER_OPTIONS],
+};
+
+const BACKBONE: CommandSpec = CommandSpec {
+    name: "backbone",
+    summary: "Show the placements and piece types every solution agrees on",
+    usage: &["talos backbone W H PIECES [OPTIONS]",
+             "talos backbone --size WxH --pieces PIECES [OPTIONS]",
+             "talos backbone --file FILE [OPTIONS]"],
+    options: &[PUZZLE_OPTIONS,
+               &[opt("timeout", "SECS", "Only look at the solutions found in SECS seconds"),
+                 opt("format", "FORMAT", "Output format: text or json (default text)")]],
+};
+
+const HEATMAP: CommandSpec = CommandSpec {
+    name: "heatmap",
+    summary: "Show how often each piece type covers each cell across all solutions",
+    usage: &["talos heatmap W H PIECES [OPTIONS]",
+             "talos heatmap --size WxH --pieces PIECES [OPTIONS]",
+             "talos heatmap --file FILE [OPTIONS]"],
+    options: &[PUZZLE_OPTIONS,
+               &[opt("timeout", "SECS", "Only count the solutions found in SECS seconds"),
+                 flag("csv", "Print one line per cell with the count of each piece type")]],
+};
+
+const COMMANDS: [&CommandSpec; 14] = [&SOLVE, &COUNT, &VERIFY, &GENERATE, &BENCH, &BATCH,
+                                      &PLAN, &PC, &RATE, &MULTISETS, &FIT, &HINT,
+                                      &BACKBONE, &HEATMAP];
+
+const BENCH_PUZZLES: [((usize, usize), &str); 4] = [
+    ((4, 4), "IIOO"),
+    ((5, 8), "IIIIJJLLSZ"),
+    ((6, 6), "IJLTTSZOO"),
+    ((4, 10), "IJJLLTTSZO"),
+];
+
+fn overview() -> String {
+    let mut out = String::from("Usage: talos COMMAND [OPTIONS]\n\nCommands:\n");
+    for command in COMMANDS {
+        out.push_str(&format!("  {:<13}{}\n", command.name, command.summary));
+    }
+    out.push_str("\nRun 'talos COMMAND --help' for the options of a command.\n\
+                  'talos W H PIECES' is kept as a shorthand for 'talos solve'.\n\
+                  \nExit codes: 0 solved, 1 no solution, 2 error, 3 aborted (--timeout).");
+    out
+}
+
+fn help(command: &CommandSpec) -> String {
+    let mut out = format!("talos {} - {}\n\nUsage: ", command.name, command.summary);
+    out.push_str(&command.usage.join("\n       "));
+    out.push_str("\n\nOptions:\n");
+    for option in command.options.iter().copied().flatten() {
+        let name = match option.value {
+            Some(value) => format!("--{} {value}", option.name),
+            None => format!("--{}", option.name),
+        };
+        out.push_str(&format!("  {name:<20}{}\n", option.help));
+    }
+    out.push_str(&format!("  {:<20}Show this help", "--help"));
+    out
+}
+
+fn usage_error(command: &CommandSpec, msg: impl std::fmt::Display) -> TalosError {
+    TalosError::usage(command.name, msg)
+}
+
+struct Matches {
+    positional: Vec<String>,
+    values: HashMap<&'static str, String>,
+}
+
+impl Matches {
+    fn flag(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|v| v.as_str())
+    }
+
+    fn parsed<T: std::str::FromStr>(&self, command: &CommandSpec, name: &str)
+                                      -> Result<Option<T>, TalosError>
+        where T::Err: std::fmt::Display {
+        match self.value(name) {
+            None => Ok(None),
+            Some(value) => value.parse().map(Some).map_err(|e| {
+                usage_error(command, format!("Invalid value for --{name}: {value} ({e})"))
+            }),
+        }
+    }
+}
+
+/// Splits `args` into positional arguments and the options of `command`.
+/// Returns `None` when help was requested.
+fn parse_args(command: &CommandSpec, args: &[String]) -> Result<Option<Matches>, TalosError> {
+    let mut matches = Matches { positional: Vec::new(), values: HashMap::new() };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--help" || arg == "-h" {
+            return Ok(None);
+        }
+        let Some(name) = arg.strip_prefix("--") else {
+            matches.positional.push(arg.clone());
+            continue;
+        };
+        let (name, inline_value) = match name.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (name, None),
+        };
+        let spec = command.options.iter().copied().flatten()
+            .find(|o| o.name == name)
+            .ok_or_else(|| usage_error(command, format!("Unrecognized option: --{name}")))?;
+        let value = match (spec.value, inline_value) {
+            (None, None) => String::new(),
+            (None, Some(_)) => {
+                return Err(usage_error(command, format!("Option --{name} takes no value")));
+            },
+            (Some(_), Some(value)) => value,
+            (Some(placeholder), None) => args.next().cloned().ok_or_else(|| {
+                usage_error(command, format!("Option --{name} expects a value ({placeholder})"))
+            })?,
+        };
+        matches.values.insert(spec.name, value);
+    }
+    Ok(Some(matches))
+}
+
+/// Parses the dimension found at char `offset` of `input`.
+fn parse_dimension(input: &str, offset: usize, value: &str) -> Result<usize, TalosError> {
+    value.parse::<usize>().map_err(|_| {
+        TalosError::parse_at(input, offset, format!("Invalid board dimension '{value}'"))
+    })
+}
+
+fn validate_size(size: (usize, usize)) -> Result<(usize, usize), TalosError> {
+    if size.0 == 0 || size.1 == 0 {
+        return Err(TalosError::InvalidBoard(format!("{}x{}: dimensions must be positive",
+                                                    size.0, size.1)));
+    }
+    if size.0*size.1 > MAX_CELLS {
+        return Err(TalosError::InvalidBoard(format!("{}x{}: boards are limited to {MAX_CELLS} cells",
+                                                    size.0, size.1)));
+    }
+    Ok(size)
+}
+
+fn parse_size(value: &str) -> Result<(usize, usize), TalosError> {
+    let (w, h) = value.split_once(['x', 'X']).ok_or_else(|| {
+        TalosError::parse_at(value, 0, "Expected a board size like 5x8")
+    })?;
+    validate_size((parse_dimension(value, 0, w)?,
+                   parse_dimension(value, w.chars().count() + 1, h)?))
+}
+
+/// Rejects puzzles whose pieces cannot exactly cover the free cells of `board`.
+fn check_area(board: &Bitmap2D, pieces: &[u32; 7]) -> Result<(), TalosError> {
+    let board_cells = board.shape.0*board.shape.1 - board.ones().len();
+    let piece_cells = 4*pieces.iter().sum::<u32>() as usize;
+    if board_cells != piece_cells {
+        return Err(TalosError::AreaMismatch { board_cells, piece_cells });
+    }
+    Ok(())
+}
+
+fn puzzle_args(command: &CommandSpec, matches: &Matches) -> Result<Puzzle, TalosError> {
+    match (matches.value("size"), matches.value("pieces"), matches.value("file"),
+           matches.positional.as_slice()) {
+        (Some(size), Some(pieces), None, []) =>
+            Ok(Puzzle::new(parse_size(size)?, parse_pieces(pieces)?)),
+        (None, None, None, [w, h, pieces]) => {
+            let size = validate_size((parse_dimension(w, 0, w)?, parse_dimension(h, 0, h)?))?;
+            Ok(Puzzle::new(size, parse_pieces(pieces)?))
+        },
+        (None, None, Some(path), []) => {
+            let puzzle = Puzzle::parse(&read_input(path)?)?;
+            validate_size(puzzle.size)?;
+            Ok(puzzle)
+        },
+        _ => Err(usage_error(command, "Expected one of W H PIECES, --size WxH --pieces PIECES \
+                                       or --file FILE")),
+    }
+}
+
+/// One-line description of a puzzle for tables and headers.
+fn puzzle_label(puzzle: &Puzzle) -> String {
+    format!("{}x{} {}", puzzle.size.0, puzzle.size.1, pieces_to_string(&puzzle.pieces))
+}
+
+/// Pre-placed pieces followed by the pieces the solver placed.
+fn with_placed<'a>(placed: &[&'a Placement], solution: &[&'a Placement]) -> Vec<&'a Placement> {
+    placed.iter().chain(solution).copied().collect()
+}
+
+/// How the solver's pieces have to go in, from --gravity, --queue and --hold.
+struct Drops {
+    mode: DropMode,
+    queue: Option<Vec<char>>,
+    hold: bool,
+}
+
+fn drops_args(command: &CommandSpec, matches: &Matches) -> Result<Option<Drops>, TalosError> {
+    let mode: Option<DropMode> = matches.parsed(command, "gravity")?;
+    let queue = match matches.value("queue") {
+        Some(queue) => {
+            parse_pieces(queue)?;
+            Some(queue.chars().map(|c| c.to_ascii_uppercase()).collect())
+        },
+        None => None,
+    };
+    let hold = matches.flag("hold");
+    if hold && queue.is_none() {
+        return Err(usage_error(command, "--hold requires --queue"));
+    }
+    Ok(match (mode, queue) {
+        (None, None) => None,
+        (mode, queue) => Some(Drops { mode: mode.unwrap_or(DropMode::Shift), queue, hold }),
+    })
+}
+
+/// Checks that the queue holds the pieces left to place.
+fn check_queue(command: &CommandSpec, drops: Option<&Drops>, remaining: &[u32; 7])
+               -> Result<(), TalosError> {
+    let Some(queue) = drops.and_then(|drops| drops.queue.as_ref()) else { return Ok(()) };
+    let queued = parse_pieces(&queue.iter().collect::<String>())?;
+    if &queued != remaining {
+        return Err(usage_error(command, format!(
+            "--queue has {} but the pieces to place are {}",
+            pieces_to_string(&queued), pieces_to_string(remaining))));
+    }
+    Ok(())
+}
+
+/// Same as `with_placed`, with the solver's pieces in drop order when
+/// `drops` is set. `None` when they cannot all be dropped onto `board`.
+fn arrange<'a>(board: &Bitmap2D, placed: &[&'a Placement], solution: &[&'a Placement],
+               drops: Option<&Drops>) -> Option<Vec<&'a Placement>> {
+    let Some(drops) = drops else { return Some(with_placed(placed, solution)) };
+    let order = match &drops.queue {
+        Some(queue) => gravity::queue_order(board, solution, queue, drops.hold, drops.mode)?,
+        None => gravity::drop_order(board, solution, drops.mode)?,
+    };
+    let dropped: Vec<&Placement> = order.into_iter().map(|idx| solution[idx]).collect();
+    Some(with_placed(placed, &dropped))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Text,
+    Box,
+    Instructions,
+    Json,
+    Ndjson,
+    Fumen,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "box" => Ok(OutputFormat::Box),
+            "instructions" => Ok(OutputFormat::Instructions),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "fumen" => Ok(OutputFormat::Fumen),
+            _ => Err(format!("Unrecognized format: {s} (expected text, box, instructions, json, \
+                              ndjson or fumen)"))
+        }
+    }
+}
+
+struct Style {
+    color: ColorSupport,
+    palette: Palette,
+    color_by: ColorBy,
+}
+
+fn style_args(command: &CommandSpec, matches: &Matches) -> Result<Style, TalosError> {
+    let color = match matches.value("color") {
+        None | Some("auto") => term::detect_color_support(),
+        Some(_) => matches.parsed(command, "color")?.unwrap(),
+    };
+    Ok(Style {
+        color,
+        palette: matches.parsed(command, "palette")?.unwrap_or(Palette::Default),
+        color_by: matches.parsed(command, "color-by")?.unwrap_or(ColorBy::Neighbour),
+    })
+}
+
+fn colored(board_size: (usize, usize), solution: &[&Placement], style: &Style) -> String {
+    let palette_size = style.palette.swatches().len();
+    let colors = match style.color_by {
+        ColorBy::Neighbour => {
+            let (colors, distinct) = render::color_pieces(board_size, solution, palette_size);
+            if !distinct {
+                eprintln!("warning: {palette_size} colors are not enough to tell every pair of \
+                           neighbouring pieces apart");
+            }
+            colors
+        },
+        ColorBy::Piece => render::piece_type_colors(solution, palette_size),
+    };
+    term::to_ansi(render::color_grid(board_size, solution, &colors), style.color, style.palette)
+}
+
+fn puzzle_json(puzzle: &Puzzle) -> Json {
+    let mut fields = vec![
+        ("shape", puzzle.size.into()),
+        ("pieces", pieces_to_string(&puzzle.pieces).into()),
+    ];
+    if !puzzle.blocked.is_empty() {
+        fields.push(("blocked", Json::Array(puzzle.blocked.iter().map(|&c| c.into()).collect())));
+    }
+    Json::object(fields)
+}
+
+/// How a command that ran to completion ended. `Solved` also covers
+/// commands with nothing to solve, such as help or bench.
+pub enum Outcome {
+    Solved,
+    Unsolvable,
+}
+
+fn outcome(found: bool) -> Outcome {
+    if found { Outcome::Solved } else { Outcome::Unsolvable }
+}
+
+fn timeout_arg(command: &CommandSpec, matches: &Matches) -> Result<Option<Duration>, TalosError> {
+    let Some(secs) = matches.parsed::<f64>(command, "timeout")? else { return Ok(None) };
+    Duration::try_from_secs_f64(secs).map(Some)
+        .map_err(|_| usage_error(command, format!("Invalid value for --timeout: {secs}")))
+}
+
+pub fn run(args: Vec<String>) -> Result<Outcome, TalosError> {
+    let Some(first) = args.first() else {
+        return Err(TalosError::Usage { command: None, message: overview() });
+    };
+    if first.parse::<usize>().is_ok() {
+        return solve(&args);
+    }
+    let rest = &args[1..];
+    match first.as_str() {
+        "--help" | "-h" => { println!("{}", overview()); Ok(Outcome::Solved) },
+        "help" => match rest.first().and_then(|name| COMMANDS.iter().find(|c| c.name == name)) {
+            Some(command) => { println!("{}", help(command)); Ok(Outcome::Solved) },
+            None => { println!("{}", overview()); Ok(Outcome::Solved) },
+        },
+        "solve" => solve(rest),
+        "count" => count(rest),
+        "verify" => verify(rest),
+        "generate" => generate(rest),
+        "bench" => bench(rest),
+        "batch" => batch(rest),
+        "plan" => plan(rest),
+        "pc" => pc(rest),
+        "rate" => rate(rest),
+        "multisets" => multisets(rest),
+        "fit" => fit(rest),
+        "hint" => hint(rest),
+        "backbone" => backbone(rest),
+        "heatmap" => heatmap(rest),
+        _ => Err(TalosError::Usage {
+            command: None,
+            message: format!("Unrecognized command: {first}\n{}", overview()),
+        }),
+    }
+}
+
+/// The puzzle's board with only its blocked cells filled.
+fn blocked_board(puzzle: &Puzzle) -> Bitmap2D {
+    let mut board = Bitmap2D::zeros(puzzle.size);
+    for &cell in &puzzle.blocked {
+        board.set(cell);
+    }
+    board
+}
+
+fn solve(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&SOLVE, args)? else {
+        println!("{}", help(&SOLVE));
+        return Ok(Outcome::Solved);
+    };
+    let puzzle = puzzle_args(&SOLVE, &matches)?;
+    let find_all = matches.flag("all") || matches.flag("find-all");
+    let format = matches.parsed(&SOLVE, "format")?.unwrap_or(OutputFormat::Text);
+    let style = style_args(&SOLVE, &matches)?;
+    let width = matches.parsed(&SOLVE, "width")?.unwrap_or_else(layout::terminal_width);
+    let page: usize = matches.parsed(&SOLVE, "page")?.unwrap_or(1);
+    let page_size: Option<usize> = matches.parsed(&SOLVE, "page-size")?;
+    let timeout = timeout_arg(&SOLVE, &matches)?;
+    let drops = drops_args(&SOLVE, &matches)?;
+
+    let save = matches.value("save");
+    if save.is_some() && find_all {
+        return Err(usage_error(&SOLVE, "--save cannot be combined with --all"));
+    }
+
+    let board_size = puzzle.size;
+    let pieces = puzzle.placement_tables();
+    let (board, remaining, placed) = puzzle.start(&pieces)?;
+    check_area(&board, &remaining)?;
+    check_queue(&SOLVE, drops.as_ref(), &remaining)?;
+    let mut solver = Solver::new(&pieces).with_timeout(timeout);
+    let aborted = |solver: &Solver| match (solver.timed_out, timeout) {
+        (true, Some(timeout)) => Err(TalosError::Timeout(timeout)),
+        _ => Ok(()),
+    };
+
+    match (find_all, format) {
+        (true, OutputFormat::Text | OutputFormat::Box | OutputFormat::Instructions) => {
+            let mut solutions = Vec::new();
+            solver.for_each(&board, remaining, |sol| {
+                solutions.extend(arrange(&board, &placed, sol, drops.as_ref()));
+                ControlFlow::Continue(())
+            });
+            let page_size = page_size.unwrap_or(solutions.len()).max(1);
+            let n_pages = solutions.len().div_ceil(page_size).max(1);
+            if page == 0 || page > n_pages {
+                return Err(usage_error(&SOLVE, format!("Page {page} out of range (1-{n_pages})")));
+            }
+            let first = (page - 1)*page_size;
+            let blocks: Vec<(usize, String)> = solutions.iter().enumerate()
+                .skip(first).take(page_size)
+                .map(|(idx, solution)| (idx + 1, match format {
+                    OutputFormat::Box => render::to_box_drawing(board_size, solution),
+                    OutputFormat::Instructions => render::to_instructions(solution),
+                    _ => colored(board_size, solution, &style),
+                }))
+                .collect();
+            print!("{}", layout::pack(&blocks, width, 2));
+            if n_pages > 1 {
+                println!("Page {page}/{n_pages} (solutions {}-{} of {})",
+                         first + 1, first + blocks.len(), solutions.len());
+            } else {
+                println!("{} solution(s)", solutions.len());
+            }
+            aborted(&solver)?;
+            Ok(outcome(!solutions.is_empty()))
+        },
+        (true, OutputFormat::Fumen) => {
+            let mut result = Ok(());
+            let mut count = 0;
+            solver.for_each(&board, remaining, |sol| {
+                let Some(sol) = arrange(&board, &placed, sol, drops.as_ref()) else {
+                    return ControlFlow::Continue(());
+                };
+                match fumen::pages(&blocked_board(&puzzle), &sol, false) {
+                    Ok(pages) => println!("{}", fumen::encode(&pages)),
+                    Err(err) => { result = Err(err); return ControlFlow::Break(()) },
+                }
+                count += 1;
+                ControlFlow::Continue(())
+            });
+            result?;
+            aborted(&solver)?;
+            Ok(outcome(count > 0))
+        },
+        (true, OutputFormat::Json) => {
+            let mut solutions = Vec::new();
+            solver.for_each(&board, remaining, |sol| {
+                if let Some(sol) = arrange(&board, &placed, sol, drops.as_ref()) {
+                    solutions.push(json::solution(&sol));
+                }
+                ControlFlow::Continue(())
+            });
+            let found = !solutions.is_empty();
+            let status = match (solver.timed_out, found) {
+                (true, _) => "aborted",
+                (false, true) => "solved",
+                (false, false) => "unsolvable",
+            };
+            println!("{}", Json::object(vec![
+                ("puzzle", puzzle_json(&puzzle)),
+                ("outcome", status.into()),
+                ("count", solutions.len().into()),
+                ("solutions", Json::Array(solutions)),
+            ]));
+            aborted(&solver)?;
+            Ok(outcome(found))
+        },
+        (true, OutputFormat::Ndjson) => {
+            let mut count = 0;
+            solver.for_each(&board, remaining, |sol| {
+                let Some(sol) = arrange(&board, &placed, sol, drops.as_ref()) else {
+                    return ControlFlow::Continue(());
+                };
+                println!("{}", Json::object(vec![
+                    ("solution", count.into()),
+                    ("placements", json::solution(&sol)),
+                ]));
+                count += 1;
+                ControlFlow::Continue(())
+            });
+            aborted(&solver)?;
+            Ok(outcome(count > 0))
+        },
+        (false, _) => {
+            let mut solution = None;
+            solver.for_each(&board, remaining, |sol| {
+                solution = arrange(&board, &placed, sol, drops.as_ref());
+                if solution.is_some() { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+            });
+            if format == OutputFormat::Json {
+                let status = match (solver.timed_out, &solution) {
+                    (true, _) => "aborted",
+                    (false, Some(_)) => "solved",
+                    (false, None) => "unsolvable",
+                };
+                println!("{}", Json::object(vec![
+                    ("puzzle", puzzle_json(&puzzle)),
+                    ("outcome", status.into()),
+                    ("placements", solution.as_deref().map_or(Json::Null, json::solution)),
+                ]));
+            }
+            aborted(&solver)?;
+            if let (Some(path), Some(sol)) = (save, &solution) {
+                fs::write(path, puzzle.solved(sol).to_string())
+                    .map_err(|source| TalosError::Io { path: path.to_string(), source })?;
+            }
+
+            match (&solution, format) {
+                (Some(sol), OutputFormat::Text | OutputFormat::Box) => {
+                    let rendered = match format {
+                        OutputFormat::Box => render::to_box_drawing(board_size, sol),
+                        _ => colored(board_size, sol, &style),
+                    };
+                    println!("Solution:\n{rendered}");
+                    if drops.is_some() {
+                        println!("Drop order:\n{}", render::to_steps(&sol[placed.len()..]));
+                    }
+                },
+                (Some(sol), OutputFormat::Instructions) => {
+                    let steps = match drops {
+                        Some(_) => render::to_steps(&sol[placed.len()..]),
+                        None => render::to_instructions(sol),
+                    };
+                    println!("Instructions:\n{steps}");
+                },
+                (Some(sol), OutputFormat::Fumen) => {
+                    println!("{}", fumen::encode(&fumen::pages(&blocked_board(&puzzle), sol, false)?));
+                },
+                (None, OutputFormat::Text | OutputFormat::Box | OutputFormat::Instructions
+                       | OutputFormat::Fumen) => {
+                    println!("No solution");
+                },
+                (Some(sol), OutputFormat::Ndjson) => {
+                    println!("{}", Json::object(vec![
+                        ("solution", 0usize.into()),
+                        ("placements", json::solution(sol)),
+                    ]));
+                },
+                (None, OutputFormat::Ndjson) | (_, OutputFormat::Json) => {},
+            }
+            Ok(outcome(solution.is_some()))
+        }
+    }
+}
+
+fn count(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&COUNT, args)? else {
+        println!("{}", help(&COUNT));
+        return Ok(Outcome::Solved);
+    };
+    let puzzle = puzzle_args(&COUNT, &matches)?;
+    let timeout = timeout_arg(&COUNT, &matches)?;
+    let drops = drops_args(&COUNT, &matches)?;
+    let pieces = puzzle.placement_tables();
+    let (board, remaining, placed) = puzzle.start(&pieces)?;
+    check_area(&board, &remaining)?;
+    check_queue(&COUNT, drops.as_ref(), &remaining)?;
+    let mut solver = Solver::new(&pieces).with_timeout(timeout);
+    let count = match drops {
+        None => solver.count(&board, remaining),
+        Some(_) => {
+            let mut count = 0;
+            solver.for_each(&board, remaining, |sol| {
+                count += usize::from(arrange(&board, &placed, sol, drops.as_ref()).is_some());
+                ControlFlow::Continue(())
+            });
+            count
+        },
+    };
+    if let (true, Some(timeout)) = (solver.timed_out, timeout) {
+        return Err(TalosError::Timeout(timeout));
+    }
+    println!("{count}");
+    Ok(outcome(count > 0))
+}
+
+/// Reads `path`, or stdin when it is "-".
+fn read_input(path: &str) -> Result<String, TalosError> {
+    if path == "-" {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)
+            .map_err(|source| TalosError::Io { path: "stdin".to_string(), source })?;
+        Ok(text)
+    } else {
+        fs::read_to_string(path).map_err(|source| TalosError::Io { path: path.to_string(), source })
+    }
+}
+
+/// Reads a letter grid, one row per line or '/'-separated, ignoring blank
+/// lines and surrounding spaces.
+/// Errors point at the row and column of the normalized grid, one row per
+/// line.
+fn parse_grid(text: &str) -> Result<Vec<Vec<char>>, TalosError> {
+    let rows: Vec<&str> = text.split(['\n', '/'])
+        .map(|row| row.trim())
+        .filter(|row| !row.is_empty())
+        .collect();
+    let error = |row_id: usize, col_id: usize, message: String| TalosError::Parse {
+        input: rows.join("\n"), line: row_id + 1, column: col_id + 1, message
+    };
+    let grid: Vec<Vec<char>> = rows.iter().map(|row| row.chars().collect()).collect();
+    let Some(width) = grid.first().map(|row| row.len()) else {
+        return Err(TalosError::parse_at(text, 0, "Empty grid"));
+    };
+    for (row_id, row) in grid.iter().enumerate() {
+        if let Some(col_id) = row.iter().position(|c| !PIECE_ORDER.contains(c) && *c != '.') {
+            return Err(error(row_id, col_id, format!("Unrecognized piece name '{}'", row[col_id])));
+        }
+        if row.len() != width {
+            return Err(error(row_id, row.len().min(width),
+                             format!("Row has {} cells, expected {width}", row.len())));
+        }
+    }
+    Ok(grid)
+}
+
+/// Splits `grid` into pieces with `segment::segment` and, when the pieces
+/// are all well formed, checks them against `expected` piece counts.
+fn check_grid<'a>(grid: &[Vec<char>], tables: &'a PaddedPieces, expected: Option<&[u32; 7]>)
+                  -> (Vec<&'a Placement>, Vec<String>) {
+    let (pieces, mut errors) = segment::segment(grid, tables);
+    if let Some(expected) = expected && errors.is_empty() {
+        let found = generate::piece_counts(&pieces);
+        for (piece_id, letter) in PIECE_ORDER.iter().enumerate() {
+            let (expected, found) = (expected[piece_id], found[piece_id]);
+            if found < expected {
+                errors.push(format!("Missing {} {letter} piece(s)", expected - found));
+            } else if found > expected {
+                errors.push(format!("{} extra {letter} piece(s)", found - expected));
+            }
+        }
+    }
+    (pieces, errors)
+}
+
+fn verify(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&VERIFY, args)? else {
+        println!("{}", help(&VERIFY));
+        return Ok(Outcome::Solved);
+    };
+    let text = match (matches.value("grid-file"), matches.positional.as_slice()) {
+        (Some(path), []) => read_input(path)?,
+        (None, [grid]) => grid.clone(),
+        _ => return Err(usage_error(&VERIFY, "Expected either GRID or --grid-file FILE")),
+    };
+    let expected = matches.value("pieces").map(parse_pieces).transpose()?;
+    let grid = parse_grid(&text)?;
+    let shape = (grid.len(), grid[0].len());
+    if let Some(size) = matches.value("size").map(parse_size).transpose()?
+        && size != shape {
+        println!("Invalid solution:\n  Grid is {}x{}, expected {}x{}",
+                 shape.0, shape.1, size.0, size.1);
+        return Ok(Outcome::Unsolvable);
+    }
+    let tables = get_padded_pieces(shape);
+    let (pieces, errors) = check_grid(&grid, &tables, expected.as_ref());
+    if errors.is_empty() {
+        println!("Valid {}x{} solution:\n{}", shape.0, shape.1, render::to_instructions(&pieces));
+    } else {
+        println!("Invalid solution:\n  {}", errors.join("\n  "));
+    }
+    Ok(outcome(errors.is_empty()))
+}
+
+fn generate(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&GENERATE, args)? else {
+        println!("{}", help(&GENERATE));
+        return Ok(Outcome::Solved);
+    };
+    let size = parse_size(matches.value("size")
+        .ok_or_else(|| usage_error(&GENERATE, "Missing --size WxH"))?)?;
+    if (size.0*size.1) % 4 != 0 {
+        return Err(TalosError::InvalidBoard(format!(
+            "{}x{} has {} cells, which tetrominoes cannot cover",
+            size.0, size.1, size.0*size.1)));
+    }
+    let mut rng = match matches.parsed(&GENERATE, "seed")? {
+        Some(seed) => Rng::new(seed),
+        None => Rng::from_time(),
+    };
+    if let Some(n) = matches.parsed::<usize>(&GENERATE, "pieces")?
+        && 4*n != size.0*size.1 {
+        return Err(TalosError::InvalidBoard(format!(
+            "{}x{} takes {} pieces, not {n}", size.0, size.1, size.0*size.1/4)));
+    }
+    let unique = matches.flag("unique");
+    let min_nodes: Option<u64> = matches.parsed(&GENERATE, "min-nodes")?;
+    let max_nodes: Option<u64> = matches.parsed(&GENERATE, "max-nodes")?;
+    let attempts: usize = matches.parsed(&GENERATE, "attempts")?.unwrap_or(1000);
+    let timeout = timeout_arg(&GENERATE, &matches)?;
+    let deadline = timeout.map(|t| Instant::now() + t);
+    let format = matches.parsed(&GENERATE, "format")?.unwrap_or(OutputFormat::Text);
+    let style = style_args(&GENERATE, &matches)?;
+
+    let board = Bitmap2D::zeros(size);
+    let pieces = get_padded_pieces(size);
+    let check = unique || min_nodes.is_some() || max_nodes.is_some();
+    let mut found = None;
+    for attempt in 0..attempts.max(1) {
+        // Few piece types make unique solutions much more likely, so every
+        // other attempt only uses some of them.
+        let mut allowed = [true; 7];
+        while check && attempt % 2 == 1
+            && !(2..=3).contains(&allowed.iter().filter(|&&a| a).count()) {
+            for allow in allowed.iter_mut() {
+                *allow = rng.below(3) == 0;
+            }
+        }
+        let tiling = generate::random_tiling(&board, (0, 0), &pieces, allowed, &mut rng, deadline)
+            .map_err(|_| TalosError::Timeout(timeout.unwrap_or_default()))?;
+        let tiling = match tiling {
+            Some(tiling) => tiling,
+            None if attempt % 2 == 1 => continue,
+            None => return Err(TalosError::InvalidBoard("no tiling exists".to_string())),
+        };
+        let mut tiling = tiling;
+        tiling.reverse();
+        if !check {
+            found = Some((tiling, None));
+            break;
+        }
+        let counts = generate::piece_counts(&tiling);
+        // A single piece type makes for a trivial puzzle.
+        if counts.iter().filter(|&&n| n > 0).count() < 2 { continue }
+        let left = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        let mut solver = Solver::new(&pieces).with_timeout(left);
+        let result = generate::unique_solution(&mut solver, &board, counts);
+        if let (true, Some(timeout)) = (solver.timed_out, timeout) {
+            return Err(TalosError::Timeout(timeout));
+        }
+        if let Some((_, nodes, is_unique)) = result
+            && (is_unique || !unique)
+            && min_nodes.is_none_or(|min| nodes >= min)
+            && max_nodes.is_none_or(|max| nodes <= max) {
+            found = Some((tiling, Some(nodes)));
+            break;
+        }
+    }
+    let Some((tiling, nodes)) = found else {
+        println!("No puzzle passed the filters in {attempts} attempt(s)");
+        return Ok(Outcome::Unsolvable);
+    };
+    let pieces_str = pieces_to_string(&generate::piece_counts(&tiling));
+    match format {
+        OutputFormat::Text => println!("Puzzle: {} {} {pieces_str}\n{}", size.0, size.1,
+                                       colored(size, &tiling, &style)),
+        OutputFormat::Box => println!("Puzzle: {} {} {pieces_str}\n{}", size.0, size.1,
+                                      render::to_box_drawing(size, &tiling)),
+        OutputFormat::Instructions => println!("Puzzle: {} {} {pieces_str}\n{}", size.0, size.1,
+                                               render::to_instructions(&tiling)),
+        OutputFormat::Fumen => println!("Puzzle: {} {} {pieces_str}\n{}", size.0, size.1,
+                                        fumen::encode(&fumen::pages(&board, &tiling, false)?)),
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let mut fields = vec![
+                ("puzzle", puzzle_json(&Puzzle::new(size, generate::piece_counts(&tiling)))),
+                ("placements", json::solution(&tiling)),
+            ];
+            if let Some(nodes) = nodes {
+                fields.extend([("nodes", (nodes as usize).into()), ("unique", unique.into())]);
+            }
+            println!("{}", Json::object(fields));
+        },
+    }
+    if let (Some(nodes), false) = (nodes, matches!(format, OutputFormat::Json | OutputFormat::Ndjson)) {
+        println!("Solver nodes: {nodes}{}", if unique { " (unique solution)" } else { "" });
+    }
+    Ok(Outcome::Solved)
+}
+
+fn bench(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&BENCH, args)? else {
+        println!("{}", help(&BENCH));
+        return Ok(Outcome::Solved);
+    };
+    let puzzles = if matches.value("size").is_some() || !matches.positional.is_empty() {
+        vec![puzzle_args(&BENCH, &matches)?]
+    } else {
+        BENCH_PUZZLES.iter()
+            .map(|&(size, pieces)| Puzzle::new(size, parse_pieces(pieces).unwrap()))
+            .collect()
+    };
+    let runs: usize = matches.parsed(&BENCH, "runs")?.unwrap_or(3).max(1);
+    let find_all = matches.flag("all");
+
+    println!("{:<20}{:>10}{:>12}{:>12}", "puzzle", "solutions", "min ms", "mean ms");
+    for puzzle in puzzles {
+        let pieces = puzzle.placement_tables();
+        let (board, remaining, _) = puzzle.start(&pieces)?;
+        let mut timings = Vec::with_capacity(runs);
+        let mut found = 0;
+        for _ in 0..runs {
+            let start = Instant::now();
+            found = if find_all {
+                all_solutions(&board, remaining, (0, 0), &pieces).len()
+            } else {
+                fill_board(&board, remaining, (0, 0), &pieces).map_or(0, |_| 1)
+            };
+            timings.push(start.elapsed());
+        }
+        let min = timings.iter().min().unwrap();
+        let mean = timings.iter().sum::<Duration>() / runs as u32;
+        println!("{:<20}{:>10}{:>12.3}{:>12.3}",
+                 puzzle_label(&puzzle),
+                 found, min.as_secs_f64()*1e3, mean.as_secs_f64()*1e3);
+    }
+    Ok(Outcome::Solved)
+}
+
+struct BatchResult {
+    line: usize,
+    puzzle: String,
+    status: &'static str,
+    solutions: Option<usize>,
+    time: Duration,
+}
+
+/// Placement tables of a batch, per board size and constraints.
+type TableCache = HashMap<((usize, usize), Vec<Constraint>), PaddedPieces>;
+
+/// Solves one batch line, reusing the placement tables cached per board size.
+/// Returns the result and the solution found, if any.
+fn solve_batch_line<'a>(line: &str, default_all: bool, default_timeout: Option<Duration>,
+                        cache: &'a mut TableCache)
+                          -> Result<(Puzzle, Option<Vec<&'a Placement>>, BatchResult), TalosError> {
+    let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+    let matches = parse_args(&BATCH_LINE, &args)?
+        .ok_or_else(|| usage_error(&BATCH, "--help is not allowed in a batch file"))?;
+    let puzzle = puzzle_args(&BATCH_LINE, &matches)?;
+    let find_all = default_all || matches.flag("all");
+    let timeout = timeout_arg(&BATCH_LINE, &matches)?.or(default_timeout);
+
+    let pieces = cache.entry((puzzle.size, puzzle.constraints.clone()))
+        .or_insert_with(|| puzzle.placement_tables());
+    let (board, remaining, placed) = puzzle.start(pieces)?;
+    check_area(&board, &remaining)?;
+    let mut solver = Solver::new(pieces).with_timeout(timeout);
+    let start = Instant::now();
+    let mut first = None;
+    let mut count = 0;
+    solver.for_each(&board, remaining, |sol| {
+        first.get_or_insert_with(|| with_placed(&placed, sol));
+        count += 1;
+        if find_all { ControlFlow::Continue(()) } else { ControlFlow::Break(()) }
+    });
+    let status = match (solver.timed_out, first.is_some()) {
+        (true, _) => "aborted",
+        (false, true) => "solved",
+        (false, false) => "unsolvable",
+    };
+    let result = BatchResult {
+        line: 0,
+        puzzle: puzzle_label(&puzzle),
+        status,
+        solutions: (find_all && !solver.timed_out).then_some(count),
+        time: start.elapsed(),
+    };
+    Ok((puzzle, first, result))
+}
+
+fn batch(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&BATCH, args)? else {
+        println!("{}", help(&BATCH));
+        return Ok(Outcome::Solved);
+    };
+    let text = match matches.positional.as_slice() {
+        [] => read_input("-")?,
+        [path] => read_input(path)?,
+        _ => return Err(usage_error(&BATCH, "Expected at most one FILE")),
+    };
+    let find_all = matches.flag("all");
+    let timeout = timeout_arg(&BATCH, &matches)?;
+    let format = matches.parsed(&BATCH, "format")?.unwrap_or(OutputFormat::Text);
+    let style = style_args(&BATCH, &matches)?;
+
+    let mut cache = HashMap::new();
+    let mut results = Vec::new();
+    let mut json_results = Vec::new();
+    for (line_id, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() { continue }
+        let result = match solve_batch_line(line, find_all, timeout, &mut cache) {
+            Ok((puzzle, solution, mut result)) => {
+                result.line = line_id + 1;
+                let placements = solution.as_deref().map_or(Json::Null, json::solution);
+                match (format, &solution) {
+                    (OutputFormat::Text, Some(sol)) => println!(
+                        "Puzzle {} (line {}): {}\n{}", results.len() + 1, result.line,
+                        result.puzzle, colored(puzzle.size, sol, &style)),
+                    (OutputFormat::Box, Some(sol)) => println!(
+                        "Puzzle {} (line {}): {}\n{}\n", results.len() + 1, result.line,
+                        result.puzzle, render::to_box_drawing(puzzle.size, sol)),
+                    (OutputFormat::Instructions, Some(sol)) => println!(
+                        "Puzzle {} (line {}): {}\n{}\n", results.len() + 1, result.line,
+                        result.puzzle, render::to_instructions(sol)),
+                    (OutputFormat::Fumen, Some(sol)) => println!(
+                        "Puzzle {} (line {}): {}\n{}\n", results.len() + 1, result.line,
+                        result.puzzle, fumen::pages(&blocked_board(&puzzle), sol, false)
+                            .map_or_else(|err| err.to_string(), |pages| fumen::encode(&pages))),
+                    (OutputFormat::Text | OutputFormat::Box | OutputFormat::Instructions
+                     | OutputFormat::Fumen, None) =>
+                        println!(
+                        "Puzzle {} (line {}): {}\nNo solution ({})\n", results.len() + 1,
+                        result.line, result.puzzle, result.status),
+                    (OutputFormat::Json | OutputFormat::Ndjson, _) => {
+                        let value = Json::object(vec![
+                            ("line", result.line.into()),
+                            ("puzzle", puzzle_json(&puzzle)),
+                            ("outcome", result.status.into()),
+                            ("count", result.solutions.map_or(Json::Null, Json::from)),
+                            ("time_ms", (result.time.as_secs_f64()*1e3).into()),
+                            ("placements", placements),
+                        ]);
+                        if format == OutputFormat::Ndjson {
+                            println!("{value}");
+                        } else {
+                            json_results.push(value);
+                        }
+                    },
+                }
+                result
+            },
+            Err(err) => {
+                let message = err.to_string();
+                let first_line = message.lines().next().unwrap_or("");
+                match format {
+                    OutputFormat::Text | OutputFormat::Box | OutputFormat::Instructions
+                    | OutputFormat::Fumen => println!(
+                        "Puzzle {} (line {}): {line}\nerror: {first_line}\n",
+                        results.len() + 1, line_id + 1),
+                    OutputFormat::Json | OutputFormat::Ndjson => {
+                        let value = Json::object(vec![
+                            ("line", (line_id + 1).into()),
+                            ("input", line.into()),
+                            ("outcome", "error".into()),
+                            ("error", first_line.into()),
+                        ]);
+                        if format == OutputFormat::Ndjson {
+                            println!("{value}");
+                        } else {
+                            json_results.push(value);
+                        }
+                    },
+                }
+                BatchResult { line: line_id + 1, puzzle: line.to_string(), status: "error",
+                              solutions: None, time: Duration::ZERO }
+            }
+        };
+        results.push(result);
+    }
+
+    match format {
+        OutputFormat::Json => println!("{}", Json::object(vec![
+            ("results", Json::Array(json_results)),
+        ])),
+        OutputFormat::Ndjson => {},
+        OutputFormat::Text | OutputFormat::Box | OutputFormat::Instructions
+        | OutputFormat::Fumen => {
+            println!("{:>4} {:>5}  {:<24}{:<12}{:>10}{:>12}",
+                     "#", "line", "puzzle", "result", "solutions", "time ms");
+            for (idx, result) in results.iter().enumerate() {
+                let solutions = match (result.solutions, result.status) {
+                    (Some(count), _) => count.to_string(),
+                    (None, "solved") => ">=1".to_string(),
+                    (None, "unsolvable") => "0".to_string(),
+                    (None, _) => "-".to_string(),
+                };
+                println!("{:>4} {:>5}  {:<24}{:<12}{:>10}{:>12.3}", idx + 1, result.line,
+                         result.puzzle, result.status, solutions,
+                         result.time.as_secs_f64()*1e3);
+            }
+        },
+    }
+    Ok(outcome(results.iter().all(|r| r.status == "solved")))
+}
+
+/// Parses a door given as WxH:PIECES or WxH.
+fn parse_door(arg: &str) -> Result<Door, TalosError> {
+    let (size_str, pieces) = match arg.split_once(':') {
+        Some((size_str, pieces_str)) => {
+            let pieces = parse_pieces(pieces_str).map_err(|err| match err {
+                TalosError::Parse { column, message, .. } =>
+                    TalosError::parse_at(arg, size_str.chars().count() + column, message),
+                err => err,
+            })?;
+            (size_str, Some(pieces))
+        },
+        None => (arg, None),
+    };
+    let size = parse_size(size_str)?;
+    let board_cells = size.0*size.1;
+    match pieces {
+        Some(pieces) if 4*pieces.iter().sum::<u32>() as usize != board_cells =>
+            Err(TalosError::AreaMismatch { board_cells,
+                                           piece_cells: 4*pieces.iter().sum::<u32>() as usize }),
+        None if board_cells % 4 != 0 => Err(TalosError::InvalidBoard(format!(
+            "{arg} has {board_cells} cells, which tetrominoes cannot cover"))),
+        _ => Ok(Door { name: arg.to_string(), size, pieces }),
+    }
+}
+
+fn plan(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&PLAN, args)? else {
+        println!("{}", help(&PLAN));
+        return Ok(Outcome::Solved);
+    };
+    let inventory = parse_pieces(matches.value("inventory")
+        .ok_or_else(|| usage_error(&PLAN, "Missing --inventory PIECES"))?)?;
+    if matches.positional.is_empty() {
+        return Err(usage_error(&PLAN, "Expected at least one DOOR"));
+    }
+    let doors = matches.positional.iter().map(|arg| parse_door(arg))
+        .collect::<Result<Vec<Door>, TalosError>>()?;
+    let format = matches.parsed(&PLAN, "format")?.unwrap_or(OutputFormat::Text);
+    if format == OutputFormat::Fumen {
+        return Err(usage_error(&PLAN, "--format fumen is not supported by plan"));
+    }
+    let style = style_args(&PLAN, &matches)?;
+
+    let mut planner = Planner::default();
+    let alone: Vec<bool> = doors.iter()
+        .map(|door| planner.open_now(door, &inventory).is_some())
+        .collect();
+    let assigned = planner.plan(&doors, inventory);
+    let left = assigned.iter().flatten().fold(inventory, |left, pieces| minus(&left, pieces));
+    let solutions: Vec<Option<(Puzzle, PaddedPieces)>> = doors.iter().zip(&assigned)
+        .map(|(door, pieces)| pieces.map(|pieces| {
+            let puzzle = Puzzle::new(door.size, pieces);
+            let tables = puzzle.placement_tables();
+            (puzzle, tables)
+        }))
+        .collect();
+    let solution_of = |idx: usize| solutions[idx].as_ref().and_then(|(puzzle, tables)| {
+        Solver::new(tables).first(&Bitmap2D::zeros(puzzle.size), puzzle.pieces)
+    });
+    let opened = assigned.iter().filter(|a| a.is_some()).count();
+    let pieces_or_any = |pieces: Option<[u32; 7]>| pieces.map_or("any".to_string(), |p| pieces_to_string(&p));
+
+    match format {
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let doors_json = doors.iter().enumerate().map(|(idx, door)| Json::object(vec![
+                ("door", door.name.as_str().into()),
+                ("shape", door.size.into()),
+                ("pieces", door.pieces.map_or(Json::Null, |p| pieces_to_string(&p).into())),
+                ("alone", alone[idx].into()),
+                ("assigned", assigned[idx].map_or(Json::Null, |p| pieces_to_string(&p).into())),
+                ("placements", solution_of(idx).as_deref().map_or(Json::Null, json::solution)),
+            ])).collect();
+            println!("{}", Json::object(vec![
+                ("inventory", pieces_to_string(&inventory).into()),
+                ("doors", Json::Array(doors_json)),
+                ("opened", opened.into()),
+                ("left", pieces_to_string(&left).into()),
+            ]));
+        },
+        OutputFormat::Text | OutputFormat::Box | OutputFormat::Instructions
+        | OutputFormat::Fumen => {
+            println!("Inventory: {} ({} pieces)\n", pieces_to_string(&inventory),
+                     inventory.iter().sum::<u32>());
+            println!("{:<16}{:>7}  {:<16}{:<7}plan", "door", "size", "pieces", "alone");
+            for (idx, door) in doors.iter().enumerate() {
+                println!("{:<16}{:>7}  {:<16}{:<7}{}", door.name,
+                         format!("{}x{}", door.size.0, door.size.1), pieces_or_any(door.pieces),
+                         if alone[idx] { "yes" } else { "no" },
+                         assigned[idx].map_or("-".to_string(), |p| pieces_to_string(&p)));
+            }
+            println!("\nPlan opens {opened} of {} door(s), pieces left: {}", doors.len(),
+                     Some(pieces_to_string(&left)).filter(|l| !l.is_empty())
+                         .unwrap_or_else(|| "none".to_string()));
+            for (idx, door) in doors.iter().enumerate() {
+                let Some(solution) = solution_of(idx) else { continue };
+                let rendered = match format {
+                    OutputFormat::Box => render::to_box_drawing(door.size, &solution),
+                    OutputFormat::Instructions => render::to_instructions(&solution),
+                    _ => colored(door.size, &solution, &style),
+                };
+                println!("\n{}:\n{}", door.name, rendered.trim_end_matches('\n'));
+            }
+        },
+    }
+    Ok(outcome(opened == doors.len()))
+}
+
+/// Reads a stack of '/'-separated rows, '#' for filled cells and '.' for
+/// empty ones, and puts it at the bottom of a `height`-row field.
+fn parse_stack(text: &str, height: usize) -> Result<Bitmap2D, TalosError> {
+    let rows: Vec<&str> = text.split('/').collect();
+    let mut board = Bitmap2D::zeros((height.max(rows.len()), tetris::WIDTH));
+    let top = board.shape.0 - rows.len();
+    let mut offset = 0;
+    for (row_id, row) in rows.iter().enumerate() {
+        for (col, c) in row.chars().enumerate() {
+            match c {
+                '#' if col < tetris::WIDTH => board.set((top + row_id, col)),
+                '.' if col < tetris::WIDTH => {},
+                '#' | '.' => return Err(TalosError::parse_at(text, offset + col, format!(
+                    "Row has more than {} cells", tetris::WIDTH))),
+                _ => return Err(TalosError::parse_at(text, offset + col, format!(
+                    "Unexpected cell '{c}', expected '#' or '.'"))),
+            }
+        }
+        let len = row.chars().count();
+        if len < tetris::WIDTH {
+            return Err(TalosError::parse_at(text, offset + len, format!(
+                "Row has {len} cells, expected {}", tetris::WIDTH)));
+        }
+        offset += len + 1;
+    }
+    Ok(board)
+}
+
+fn pc(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&PC, args)? else {
+        println!("{}", help(&PC));
+        return Ok(Outcome::Solved);
+    };
+    let page = matches.value("fumen").map(fumen::decode).transpose()?;
+    let (fumen_hold, fumen_queue) = page.as_ref().and_then(|page| page.queue())
+        .unwrap_or((None, Vec::new()));
+    let queue: Vec<char> = match matches.value("queue") {
+        Some(queue_str) => {
+            parse_pieces(queue_str)?;
+            queue_str.chars().map(|c| c.to_ascii_uppercase()).collect()
+        },
+        None if !fumen_queue.is_empty() => fumen_queue,
+        None => return Err(usage_error(&PC, "Missing --queue PIECES")),
+    };
+    let held = match matches.value("held") {
+        Some(held) if parse_pieces(held)?.iter().sum::<u32>() == 1 =>
+            Some(held.chars().next().unwrap().to_ascii_uppercase()),
+        Some(held) => return Err(usage_error(&PC, format!("Expected a single piece, got {held}"))),
+        None if matches.value("queue").is_none() => fumen_hold,
+        None => None,
+    };
+    let use_hold = matches.flag("hold") || held.is_some();
+    let height = matches.parsed(&PC, "height")?.unwrap_or(4);
+    let board = match (matches.value("stack"), &page) {
+        (Some(_), Some(_)) => return Err(usage_error(&PC, "--stack cannot be combined with --fumen")),
+        (Some(stack), None) => parse_stack(stack, height)?,
+        (None, Some(page)) => page.stack(height),
+        (None, None) => Bitmap2D::zeros((height, tetris::WIDTH)),
+    };
+    validate_size(board.shape)?;
+    let format = matches.parsed(&PC, "format")?.unwrap_or(OutputFormat::Text);
+    let timeout = timeout_arg(&PC, &matches)?;
+
+    let tables = get_padded_pieces(board.shape);
+    let mode = if matches.flag("srs") { DropMode::Srs } else { DropMode::Shift };
+    let mut search = PerfectClear::new(&tables, use_hold).with_drop_mode(mode)
+        .with_timeout(timeout);
+    let steps = search.find(&board, &queue, held);
+    if let (true, Some(timeout)) = (search.timed_out, timeout) {
+        return Err(TalosError::Timeout(timeout));
+    }
+    match format {
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let steps_json = steps.as_deref().map_or(Json::Null, |steps| {
+                Json::Array(steps.iter().map(|step| Json::object(vec![
+                    ("placement", json::placement(step.placement)),
+                    ("hold", step.from_hold.into()),
+                    ("cleared", step.cleared.into()),
+                ])).collect())
+            });
+            println!("{}", Json::object(vec![
+                ("outcome", if steps.is_some() { "solved" } else { "unsolvable" }.into()),
+                ("nodes", (search.nodes as usize).into()),
+                ("steps", steps_json),
+            ]));
+        },
+        OutputFormat::Fumen => match &steps {
+            Some(steps) => {
+                let placements: Vec<&Placement> = steps.iter().map(|s| s.placement).collect();
+                let mut pages = fumen::pages(&board, &placements, true)?;
+                let hold: String = held.into_iter().collect();
+                let current: String = queue.iter().take(1).collect();
+                let next: String = queue.iter().skip(1).collect();
+                pages[0].comment = format!("#Q=[{hold}]({current}){next}");
+                println!("{}", fumen::encode(&pages));
+            },
+            None => println!("No perfect clear"),
+        },
+        _ => match &steps {
+            Some(steps) => {
+                println!("Perfect clear in {} piece(s), rows counted from the top of the \
+                          field before each drop:", steps.len());
+                let placements: Vec<&Placement> = steps.iter().map(|s| s.placement).collect();
+                for (line, step) in render::to_steps(&placements).lines().zip(steps) {
+                    let mut notes = Vec::new();
+                    if step.from_hold { notes.push("from hold".to_string()) }
+                    if step.cleared > 0 { notes.push(format!("clears {}", step.cleared)) }
+                    if notes.is_empty() {
+                        println!("{line}");
+                    } else {
+                        println!("{line}  ({})", notes.join(", "));
+                    }
+                }
+            },
+            None => println!("No perfect clear"),
+        },
+    }
+    Ok(outcome(steps.is_some()))
+}
+
+fn rating_json(rating: &Rating) -> Vec<(&'static str, Json)> {
+    vec![
+        ("nodes", (rating.nodes as usize).into()),
+        ("solutions", rating.solutions.into()),
+        ("complete", rating.complete.into()),
+        ("branching", rating.branching.into()),
+        ("forced", rating.forced.into()),
+        ("score", rating.score.into()),
+    ]
+}
+
+fn rate(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&RATE, args)? else {
+        println!("{}", help(&RATE));
+        return Ok(Outcome::Solved);
+    };
+    let timeout = timeout_arg(&RATE, &matches)?;
+    let json = matches!(matches.parsed(&RATE, "format")?, Some(OutputFormat::Json | OutputFormat::Ndjson));
+    let puzzle = puzzle_args(&RATE, &matches)?;
+    let tables = puzzle.placement_tables();
+    let (board, remaining, _) = puzzle.start(&tables)?;
+    check_area(&board, &remaining)?;
+    let rating = analysis::rate(&tables, &board, remaining, timeout);
+    // Counts stop at the timeout.
+    let at_least = |n: u64| if rating.complete { n.to_string() } else { format!(">={n}") };
+
+    if json {
+        let mut fields = vec![("puzzle", puzzle_json(&puzzle))];
+        fields.extend(rating_json(&rating));
+        println!("{}", Json::object(fields));
+    } else {
+        println!("Puzzle:             {}", puzzle_label(&puzzle));
+        println!("Nodes:              {}", at_least(rating.nodes));
+        println!("Solutions:          {}", at_least(rating.solutions as u64));
+        println!("Root branching:     {}", rating.branching);
+        println!("Forced placements:  {}", rating.forced);
+        println!("Difficulty:         {:.1}", rating.score);
+    }
+    match (rating.solutions, rating.complete, timeout) {
+        (0, false, Some(timeout)) => Err(TalosError::Timeout(timeout)),
+        (solutions, _, _) => Ok(outcome(solutions > 0)),
+    }
+}
+
+fn multisets(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&MULTISETS, args)? else {
+        println!("{}", help(&MULTISETS));
+        return Ok(Outcome::Solved);
+    };
+    let size = match (matches.value("size"), matches.positional.as_slice()) {
+        (Some(size), []) => parse_size(size)?,
+        (None, [w, h]) => validate_size((parse_dimension(w, 0, w)?, parse_dimension(h, 0, h)?))?,
+        _ => return Err(usage_error(&MULTISETS, "Expected W H or --size WxH")),
+    };
+    let json = matches!(matches.parsed(&MULTISETS, "format")?,
+                        Some(OutputFormat::Json | OutputFormat::Ndjson));
+    let timeout = timeout_arg(&MULTISETS, &matches)?;
+    let deadline = timeout.map(|t| Instant::now() + t);
+
+    let tables = get_padded_pieces(size);
+    let mut counts: Vec<([u32; 7], u64)> =
+        multisets::tilings_per_multiset(&Bitmap2D::zeros(size), &tables, deadline)
+            .map_err(|_| TalosError::Timeout(timeout.unwrap_or_default()))?
+            .into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.cmp(&a.0)));
+    let tilings: u64 = counts.iter().map(|(_, n)| n).sum();
+    if json {
+        let multisets_json = counts.iter().map(|(pieces, n)| Json::object(vec![
+            ("pieces", pieces_to_string(pieces).into()),
+            ("solutions", (*n as usize).into()),
+        ])).collect();
+        println!("{}", Json::object(vec![
+            ("shape", size.into()),
+            ("multisets", Json::Array(multisets_json)),
+            ("tilings", (tilings as usize).into()),
+        ]));
+    } else {
+        println!("{:<16}{:>10}", "pieces", "solutions");
+        for (pieces, n) in &counts {
+            println!("{:<16}{:>10}", pieces_to_string(pieces), n);
+        }
+        println!("{} multiset(s), {tilings} tiling(s)", counts.len());
+    }
+    Ok(outcome(!counts.is_empty()))
+}
+
+/// Board sizes with `area` cells, fewest rows first, only those with at
+/// most as many rows as columns unless `all_orientations` is set.
+fn rectangles(area: usize, all_orientations: bool) -> Vec<(usize, usize)> {
+    (1..=area).filter(|rows| area.is_multiple_of(*rows))
+        .map(|rows| (rows, area / rows))
+        .filter(|&(rows, cols)| all_orientations || rows <= cols)
+        .collect()
+}
+
+fn fit(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&FIT, args)? else {
+        println!("{}", help(&FIT));
+        return Ok(Outcome::Solved);
+    };
+    let [pieces_str] = matches.positional.as_slice() else {
+        return Err(usage_error(&FIT, "Expected PIECES"));
+    };
+    let pieces = parse_pieces(pieces_str)?;
+    let area = 4*pieces.iter().sum::<u32>() as usize;
+    if area == 0 {
+        return Err(usage_error(&FIT, "Expected at least one piece"));
+    }
+    validate_size((1, area))?;
+    let timeout = timeout_arg(&FIT, &matches)?;
+    let format = matches.parsed(&FIT, "format")?.unwrap_or(OutputFormat::Text);
+    if format == OutputFormat::Fumen {
+        return Err(usage_error(&FIT, "--format fumen is not supported by fit"));
+    }
+    let style = style_args(&FIT, &matches)?;
+
+    let mut results = Vec::new();
+    for size in rectangles(area, matches.flag("all-orientations")) {
+        let puzzle = Puzzle::new(size, pieces);
+        let tables = puzzle.placement_tables();
+        let mut solver = Solver::new(&tables).with_timeout(timeout);
+        let solution = solver.first(&Bitmap2D::zeros(size), pieces);
+        let status = match (&solution, solver.timed_out) {
+            (Some(_), _) => "solved",
+            (None, true) => "aborted",
+            (None, false) => "unsolvable",
+        };
+        let placements = solution.as_deref().map_or(Json::Null, json::solution);
+        let rendered = solution.as_deref().map(|sol| match format {
+            OutputFormat::Box => render::to_box_drawing(size, sol),
+            OutputFormat::Instructions => render::to_instructions(sol),
+            _ => colored(size, sol, &style),
+        });
+        results.push((size, status, placements, rendered));
+    }
+    let tiled = results.iter().filter(|(_, status, _, _)| *status == "solved").count();
+
+    if matches!(format, OutputFormat::Json | OutputFormat::Ndjson) {
+        let rectangles_json = results.into_iter().map(|(size, status, placements, _)| {
+            Json::object(vec![
+                ("shape", size.into()),
+                ("outcome", status.into()),
+                ("placements", placements),
+            ])
+        }).collect();
+        println!("{}", Json::object(vec![
+            ("pieces", pieces_to_string(&pieces).into()),
+            ("rectangles", Json::Array(rectangles_json)),
+        ]));
+    } else {
+        println!("Pieces: {} ({} pieces, {area} cells)", pieces_to_string(&pieces), area / 4);
+        for (size, status, _, rendered) in &results {
+            match rendered {
+                Some(rendered) => println!("\n{}x{}:\n{}", size.0, size.1, rendered.trim_end()),
+                None => println!("\n{}x{}: {}", size.0, size.1, match *status {
+                    "aborted" => "gave up (--timeout)",
+                    _ => "no tiling",
+                }),
+            }
+        }
+        println!("\n{tiled} of {} rectangle(s) tiled", results.len());
+    }
+    Ok(outcome(tiled > 0))
+}
+
+fn hint(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&HINT, args)? else {
+        println!("{}", help(&HINT));
+        return Ok(Outcome::Solved);
+    };
+    let puzzle = puzzle_args(&HINT, &matches)?;
+    let apply = match (matches.flag("apply"), matches.value("file")) {
+        (false, _) => None,
+        (true, Some(path)) if path != "-" => Some(path),
+        (true, _) => return Err(usage_error(&HINT, "--apply needs a puzzle --file")),
+    };
+    let timeout = timeout_arg(&HINT, &matches)?;
+    let format = matches.parsed(&HINT, "format")?.unwrap_or(OutputFormat::Text);
+    if format == OutputFormat::Fumen {
+        return Err(usage_error(&HINT, "--format fumen is not supported by hint"));
+    }
+    let style = style_args(&HINT, &matches)?;
+
+    let tables = puzzle.placement_tables();
+    let (board, remaining, placed) = puzzle.start(&tables)?;
+    check_area(&board, &remaining)?;
+    let total = puzzle.pieces.iter().sum::<u32>() as usize;
+    if placed.len() == total {
+        println!("Every piece is already placed");
+        return Ok(Outcome::Solved);
+    }
+    let mut solver = Solver::new(&tables).with_timeout(timeout);
+    let hint = analysis::hint(&tables, &mut solver, &board, remaining);
+    if let (Some(hint), Some(path)) = (&hint, apply) {
+        let mut text = read_input(path)?;
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        let p = hint.placement;
+        text.push_str(&format!("place {} {} {} {}\n", p.piece, p.variant, p.origin.0, p.origin.1));
+        fs::write(path, text).map_err(|source| TalosError::Io { path: path.to_string(), source })?;
+    }
+    let aborted = hint.is_none() && solver.timed_out;
+
+    if matches!(format, OutputFormat::Json | OutputFormat::Ndjson) {
+        let mut fields = vec![("puzzle", puzzle_json(&puzzle)),
+                              ("outcome", match (&hint, aborted) {
+                                  (Some(_), _) => "solved",
+                                  (None, true) => "aborted",
+                                  (None, false) => "unsolvable",
+                              }.into())];
+        if let Some(hint) = &hint {
+            let (kind, cell) = match hint.kind {
+                HintKind::Forced(cell) => ("forced", cell.into()),
+                HintKind::Common => ("common", Json::Null),
+                HintKind::Possible => ("possible", Json::Null),
+            };
+            fields.extend([("placement", json::placement(hint.placement)),
+                           ("kind", kind.into()),
+                           ("cell", cell),
+                           ("solutions", hint.solutions.map_or(Json::Null, Json::from))]);
+        }
+        println!("{}", Json::object(fields));
+    } else if let Some(hint) = &hint {
+        let p = hint.placement;
+        let (row, col) = render::top_left(p);
+        println!("Piece {} of {total}: {}, {}, row {row}, column {col}",
+                 placed.len() + 1, p.piece, render::turns(p.rotation));
+        println!("{}", match hint.kind {
+            HintKind::Forced((row, col)) =>
+                format!("It is the only piece that fits at row {row}, column {col}."),
+            HintKind::Common => match hint.solutions {
+                Some(solutions) => format!("All {solutions} solutions use it."),
+                None => "Every solution found before the timeout uses it.".to_string(),
+            },
+            HintKind::Possible =>
+                "No placement is common to all solutions; this one leads to a solution.".to_string(),
+        });
+        let shown = with_placed(&placed, &[p]);
+        let rendered = match format {
+            OutputFormat::Box => render::to_box_drawing(puzzle.size, &shown),
+            OutputFormat::Instructions => render::to_instructions(&shown),
+            _ => colored(puzzle.size, &shown, &style),
+        };
+        println!("{}", rendered.trim_end_matches('\n'));
+        if apply.is_some() {
+            println!("Added 'place {} {} {} {}' to the puzzle file",
+                     p.piece, p.variant, p.origin.0, p.origin.1);
+        }
+    } else if !aborted {
+        println!("No solution");
+    }
+    match (&hint, aborted, timeout) {
+        (None, true, Some(timeout)) => Err(TalosError::Timeout(timeout)),
+        (hint, _, _) => Ok(outcome(hint.is_some())),
+    }
+}
+
+/// One character per cell: the piece letter where every solution has the
+/// same placement, lowercase where only the piece type is always the same,
+/// else the number of piece types seen there. Blocked cells are '#'.
+fn backbone_grid(board: &Bitmap2D, placed: &[&Placement], backbone: &Backbone) -> Vec<Vec<char>> {
+    let (rows, cols) = board.shape;
+    let mut grid: Vec<Vec<char>> = (0..rows).map(|row| (0..cols).map(|col| {
+        let types = &backbone.cell_types[row*cols + col];
+        match types.iter().filter(|&&t| t).count() {
+            0 => '#',
+            1 => PIECE_ORDER[types.iter().position(|&t| t).unwrap()].to_ascii_lowercase(),
+            n => char::from_digit(n as u32, 10).unwrap(),
+        }
+    }).collect()).collect();
+    for placement in placed.iter().chain(&backbone.placements) {
+        for (row, col) in placement.bitmap.ones() {
+            grid[row][col] = placement.piece;
+        }
+    }
+    grid
+}
+
+fn backbone(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&BACKBONE, args)? else {
+        println!("{}", help(&BACKBONE));
+        return Ok(Outcome::Solved);
+    };
+    let puzzle = puzzle_args(&BACKBONE, &matches)?;
+    let timeout = timeout_arg(&BACKBONE, &matches)?;
+    let json = matches!(matches.parsed(&BACKBONE, "format")?,
+                        Some(OutputFormat::Json | OutputFormat::Ndjson));
+
+    let tables = puzzle.placement_tables();
+    let (board, remaining, placed) = puzzle.start(&tables)?;
+    check_area(&board, &remaining)?;
+    let mut solver = Solver::new(&tables).with_timeout(timeout);
+    let backbone = analysis::backbone(&mut solver, &board, remaining);
+    let complete = !solver.timed_out;
+
+    if json {
+        let mut fields = vec![("puzzle", puzzle_json(&puzzle)),
+                              ("outcome", match (&backbone, complete) {
+                                  (Some(_), _) => "solved",
+                                  (None, false) => "aborted",
+                                  (None, true) => "unsolvable",
+                              }.into())];
+        if let Some(backbone) = &backbone {
+            let (rows, cols) = puzzle.size;
+            let cells = (0..rows).map(|row| Json::Array((0..cols).map(|col| {
+                let types: String = backbone.cell_types[row*cols + col].iter().zip(PIECE_ORDER)
+                    .filter(|(t, _)| **t)
+                    .map(|(_, piece)| piece)
+                    .collect();
+                if types.is_empty() { Json::Null } else { types.into() }
+            }).collect())).collect();
+            fields.extend([("solutions", backbone.solutions.into()),
+                           ("complete", complete.into()),
+                           ("backbone", json::solution(&backbone.placements)),
+                           ("cells", Json::Array(cells))]);
+        }
+        println!("{}", Json::object(fields));
+    } else if let Some(backbone) = &backbone {
+        let grid = backbone_grid(&board, &placed, backbone);
+        println!("Puzzle:     {}", puzzle_label(&puzzle));
+        println!("Solutions:  {}{}", backbone.solutions,
+                 if complete { "" } else { " (found before the timeout)" });
+        println!("Backbone:   {} of {} piece(s)", backbone.placements.len(),
+                 remaining.iter().sum::<u32>());
+        if !backbone.placements.is_empty() {
+            println!("{}", render::to_instructions(&backbone.placements));
+        }
+        println!();
+        for row in &grid {
+            let line: Vec<String> = row.iter().map(char::to_string).collect();
+            println!("{}", line.join(" "));
+        }
+        println!("\nI: same placement in every solution, i: same piece type, \
+                  2-7: piece types seen, #: blocked");
+    } else {
+        println!("{}", if complete { "No solution" } else { "No solution found before the timeout" });
+    }
+    match (&backbone, complete, timeout) {
+        (None, false, Some(timeout)) => Err(TalosError::Timeout(timeout)),
+        (backbone, _, _) => Ok(outcome(backbone.is_some())),
+    }
+}
+
+fn heatmap(args: &[String]) -> Result<Outcome, TalosError> {
+    let Some(matches) = parse_args(&HEATMAP, args)? else {
+        println!("{}", help(&HEATMAP));
+        return Ok(Outcome::Solved);
+    };
+    let puzzle = puzzle_args(&HEATMAP, &matches)?;
+    let timeout = timeout_arg(&HEATMAP, &matches)?;
+
+    let tables = puzzle.placement_tables();
+    let (board, remaining, _) = puzzle.start(&tables)?;
+    check_area(&board, &remaining)?;
+    let mut solver = Solver::new(&tables).with_timeout(timeout);
+    let heatmap = analysis::heatmap(&mut solver, &board, remaining);
+    let (rows, cols) = puzzle.size;
+
+    if matches.flag("csv") {
+        let header: Vec<String> = PIECE_ORDER.iter().map(char::to_string).collect();
+        println!("row,col,{}", header.join(","));
+        for (cell, counts) in heatmap.counts.iter().enumerate() {
+            let counts: Vec<String> = counts.iter().map(u64::to_string).collect();
+            println!("{},{},{}", cell / cols, cell % cols, counts.join(","));
+        }
+    } else {
+        println!("Puzzle:     {}", puzzle_label(&puzzle));
+        println!("Solutions:  {}{}", heatmap.solutions,
+                 if solver.timed_out { " (found before the timeout)" } else { "" });
+        for (piece_id, &piece) in PIECE_ORDER.iter().enumerate() {
+            if remaining[piece_id] == 0 || heatmap.solutions == 0 { continue }
+            println!("\n{piece}, % of solutions covering each cell:");
+            for row in 0..rows {
+                let line: String = (0..cols).map(|col| {
+                    let count = heatmap.counts[row*cols + col][piece_id];
+                    match (board.get((row, col)).unwrap_or(true), count) {
+                        (true, _) => "   #".to_string(),
+                        (false, 0) => "   .".to_string(),
+                        (false, _) => format!("{:>4}", (100.0*count as f64 / heatmap.solutions as f64).round()),
+                    }
+                }).collect();
+                println!("{line}");
+            }
+        }
+        if heatmap.solutions == 0 {
+            println!("No solution");
+        }
+    }
+    match (heatmap.solutions, solver.timed_out, timeout) {
+        (0, true, Some(timeout)) => Err(TalosError::Timeout(timeout)),
+        (solutions, _, _) => Ok(outcome(solutions > 0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_options() {
+        let matches = parse_args(&SOLVE, &args("--size=5x8 --pieces IIIIJJLLSZ --all")).unwrap()
+            .unwrap();
+        assert_eq!(matches.value("size"), Some("5x8"));
+        assert_eq!(matches.value("pieces"), Some("IIIIJJLLSZ"));
+        assert!(matches.flag("all"));
+        assert!(!matches.flag("find-all"));
+        assert!(parse_args(&SOLVE, &args("--pieces")).is_err());
+        assert!(parse_args(&SOLVE, &args("--all=yes")).is_err());
+        assert!(parse_args(&SOLVE, &args("--seed 3")).is_err());
+        assert!(parse_args(&SOLVE, &args("4 4 IIII --help")).unwrap().is_none());
+    }
+
+    #[test]
+    fn drop_options() {
+        let matches = parse_args(&SOLVE, &args("3 4 IIO --queue iio --hold")).unwrap().unwrap();
+        let drops = drops_args(&SOLVE, &matches).unwrap().unwrap();
+        assert_eq!((drops.mode, drops.queue, drops.hold),
+                   (DropMode::Shift, Some(vec!['I', 'I', 'O']), true));
+        let matches = parse_args(&SOLVE, &args("3 4 IIO --hold")).unwrap().unwrap();
+        assert!(drops_args(&SOLVE, &matches).is_err());
+        let matches = parse_args(&SOLVE, &args("3 4 IIO")).unwrap().unwrap();
+        assert!(drops_args(&SOLVE, &matches).unwrap().is_none());
+        assert!(check_queue(&SOLVE, None, &[0, 2, 0, 0, 0, 0, 1]).is_ok());
+    }
+
+    #[test]
+    fn puzzle_from_args() {
+        let matches = parse_args(&SOLVE, &args("5 8 IIIIJJLLSZ")).unwrap().unwrap();
+        let puzzle = puzzle_args(&SOLVE, &matches).unwrap();
+        assert_eq!(puzzle.size, (5, 8));
+        assert_eq!(puzzle.pieces, [2, 4, 2, 0, 1, 1, 0]);
+
+        for line in ["0 8 IIII", "4 IIII", "--size 4 --pieces IIII", "--size 4x4",
+                     "4 4 IIIX", "--size 100x100 --pieces I",
+                     "--file puzzle.txt 4 4 IIII"] {
+            let matches = parse_args(&SOLVE, &args(line)).unwrap().unwrap();
+            assert!(puzzle_args(&SOLVE, &matches).is_err(), "{line}");
+        }
+    }
+
+    #[test]
+    fn doors() {
+        assert_eq!(parse_door("2x4").unwrap().pieces, None);
+        assert_eq!(parse_door("2x4:OO").unwrap().pieces, Some([0, 0, 0, 0, 0, 0, 2]));
+        assert_eq!(parse_door("2x4:OO").unwrap().size, (2, 4));
+        assert!(matches!(parse_door("2x4:OX"), Err(TalosError::Parse { column: 6, .. })));
+        assert!(matches!(parse_door("2x4:O"), Err(TalosError::AreaMismatch { .. })));
+        assert!(matches!(parse_door("3x3"), Err(TalosError::InvalidBoard(_))));
+        assert!(matches!(parse_door("A1-door"), Err(TalosError::Parse { .. })));
+    }
+
+    #[test]
+    fn stacks() {
+        let board = parse_stack("#########./##.#######", 4).unwrap();
+        assert_eq!(board.shape, (4, 10));
+        assert_eq!(board.ones().len(), 18);
+        assert!(board.get((2, 0)).unwrap() && !board.get((3, 2)).unwrap());
+        assert!(matches!(parse_stack("####", 4), Err(TalosError::Parse { column: 5, .. })));
+        assert!(matches!(parse_stack("#########x", 4), Err(TalosError::Parse { column: 10, .. })));
+    }
+
+    #[test]
+    fn size_errors() {
+        assert_eq!(parse_size("5x8").unwrap(), (5, 8));
+        assert!(matches!(parse_size("5y8"), Err(TalosError::Parse { column: 1, .. })));
+        assert!(matches!(parse_size("5xa"), Err(TalosError::Parse { column: 3, .. })));
+        assert!(matches!(parse_size("0x8"), Err(TalosError::InvalidBoard(_))));
+
+        let board = Bitmap2D::zeros((4, 4));
+        assert!(check_area(&board, &parse_pieces("IIII").unwrap()).is_ok());
+        assert!(matches!(check_area(&board, &parse_pieces("III").unwrap()),
+                         Err(TalosError::AreaMismatch { board_cells: 16, piece_cells: 12 })));
+    }
+
+    #[test]
+    fn outcomes() {
+        assert!(matches!(run(args("4 4 IIII --format json")), Ok(Outcome::Solved)));
+        assert!(matches!(run(args("count 4 4 IIIO")), Ok(Outcome::Unsolvable)));
+        assert!(matches!(run(args("solve 4 4 III")), Err(TalosError::AreaMismatch { .. })));
+        assert!(matches!(run(args("frobnicate")), Err(TalosError::Usage { .. })));
+        let err = run(args("count 8 8 JJJJIIIILLLLOOOO --timeout 0")).err().unwrap();
+        assert_eq!(err.exit_code(), crate::error::EXIT_ABORTED);
+    }
+
+    #[test]
+    fn batch_lines() {
+        let mut cache = HashMap::new();
+        let (_, solution, result) = solve_batch_line("4 4 IIOO --all", false, None, &mut cache)
+            .unwrap();
+        assert!(solution.is_some());
+        assert_eq!(result.status, "solved");
+        assert_eq!(result.solutions, Some(6));
+        let (_, solution, result) = solve_batch_line("--size 4x4 --pieces IIIO", false, None,
+                                                     &mut cache).unwrap();
+        assert!(solution.is_none());
+        assert_eq!(result.status, "unsolvable");
+        assert_eq!(result.solutions, None);
+        assert_eq!(cache.len(), 1);
+        assert!(solve_batch_line("4 4 IIII --format box", false, None, &mut cache).is_err());
+    }
+
+    #[test]
+    fn grid_checks() {
+        let grid = parse_grid("IIII\nOOLL/OOLI\n").unwrap();
+        assert_eq!(grid.len(), 3);
+        let tables = get_padded_pieces((3, 4));
+        assert_eq!(check_grid(&grid, &tables, None).1.len(), 2);
+        assert!(matches!(parse_grid("III/II"), Err(TalosError::Parse { line: 2, column: 3, .. })));
+        assert!(matches!(parse_grid("IIX"), Err(TalosError::Parse { line: 1, column: 3, .. })));
+
+        // The Is cannot be split into pieces, whatever their count.
+        let grid = parse_grid("IIII/OOII/OOII").unwrap();
+        assert_eq!(check_grid(&grid, &tables, Some(&parse_pieces("IIO").unwrap())).1.len(), 1);
+
+        let grid = parse_grid("IIII/IIII/OOOO/OOOO").unwrap();
+        let tables = get_padded_pieces((4, 4));
+        let (pieces, errors) = check_grid(&grid, &tables, Some(&parse_pieces("IIOO").unwrap()));
+        assert!(errors.is_empty() && pieces.len() == 4);
+        assert_eq!(check_grid(&grid, &tables, Some(&parse_pieces("IOOT").unwrap())).1,
+                   ["1 extra I piece(s)", "Missing 1 T piece(s)"]);
+    }
+
+    #[test]
+    fn fit_rectangles() {
+        assert_eq!(rectangles(32, false), vec![(1, 32), (2, 16), (4, 8)]);
+        assert_eq!(rectangles(8, true), vec![(1, 8), (2, 4), (4, 2), (8, 1)]);
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Everything that can make a command fail, as opposed to a puzzle simply
+/// having no solution.
+#[derive(Debug)]
+pub enum TalosError {
+    /// Bad command line; `command` points to the relevant `--help`.
+    Usage { command: Option<&'static str>, message: String },
+    /// Malformed input, with the 1-based line and column of the offending
+    /// character.
+    Parse { input: String, line: usize, column: usize, message: String },
+    InvalidBoard(String),
+    AreaMismatch { board_cells: usize, piece_cells: usize },
+    Timeout(Duration),
+    Io { path: String, source: std::io::Error },
+}
+
+impl TalosError {
+    pub fn usage(command: &'static str, message: impl fmt::Display) -> Self {
+        TalosError::Usage { command: Some(command), message: message.to_string() }
+    }
+
+    /// Parse error at char offset `offset` of a single-line `input`.
+    pub fn parse_at(input: &str, offset: usize, message: impl fmt::Display) -> Self {
+        TalosError::Parse { input: input.to_string(), line: 1, column: offset + 1,
+                            message: message.to_string() }
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            TalosError::Timeout(_) => EXIT_ABORTED,
+            _ => EXIT_ERROR,
+        }
+    }
+}
+
+pub const EXIT_SOLVED: u8 = 0;
+pub const EXIT_UNSOLVABLE: u8 = 1;
+pub const EXIT_ERROR: u8 = 2;
+pub const EXIT_ABORTED: u8 = 3;
+
+impl fmt::Display for TalosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TalosError::Usage { command: Some(command), message } =>
+                write!(f, "{message}\nRun 'talos {command} --help' for usage."),
+            TalosError::Usage { command: None, message } => f.write_str(message),
+            TalosError::Parse { input, line, column, message } => {
+                write!(f, "{message} at line {line}, column {column}")?;
+                if let Some(source_line) = input.lines().nth(line - 1) {
+                    write!(f, "\n  {source_line}\n  {}^", " ".repeat(column - 1))?;
+                }
+                Ok(())
+            },
+            TalosError::InvalidBoard(message) => write!(f, "Invalid board: {message}"),
+            TalosError::AreaMismatch { board_cells, piece_cells } =>
+                write!(f, "Pieces cover {piece_cells} cells but the board has {board_cells} free cells"),
+            TalosError::Timeout(timeout) =>
+                write!(f, "Search aborted after {:.3}s", timeout.as_secs_f64()),
+            TalosError::Io { path, source } => write!(f, "Cannot access {path}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for TalosError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TalosError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_error_points_at_column() {
+        let err = TalosError::parse_at("IIXO", 2, "Unrecognized piece name 'X'");
+        assert_eq!(err.to_string(),
+                   "Unrecognized piece name 'X' at line 1, column 3\n  IIXO\n    ^");
+        assert_eq!(err.exit_code(), EXIT_ERROR);
+        assert_eq!(TalosError::Timeout(Duration::from_secs(1)).exit_code(), EXIT_ABORTED);
+    }
+}
@@ -0,0 +1,515 @@
+use crate::bitmap::Bitmap2D;
+use crate::error::TalosError;
+use crate::piece::Placement;
+
+const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// Comments are made of printable ASCII once escaped, one base-96 digit per
+/// character starting from ' '.
+const COMMENT_BASE: u32 = 96;
+const MAX_COMMENT: usize = 64*64 - 1;
+
+/// Columns of a fumen field.
+pub const WIDTH: usize = 10;
+/// Visible rows of a fumen field, above its garbage row.
+pub const HEIGHT: usize = 23;
+const BLOCKS: usize = WIDTH*(HEIGHT + 1);
+/// Piece types by cell value, starting at 1 since 0 is an empty cell.
+const PIECES: [char; 7] = ['I', 'L', 'O', 'Z', 'T', 'J', 'S'];
+/// Cell value of garbage.
+pub const GARBAGE: u8 = 8;
+
+/// One page of a fumen: the field, the piece moved on it and a comment.
+pub struct Page {
+    /// Cell values row by row from the top of the field, ending with the
+    /// garbage row below it.
+    pub field: [u8; BLOCKS],
+    pub piece: Option<Piece>,
+    pub comment: String,
+}
+
+/// A piece on a page, locked into the field before the next page.
+#[derive(Debug, PartialEq)]
+pub struct Piece {
+    pub kind: char,
+    /// Clockwise quarter turns from the spawn orientation.
+    pub rotation: usize,
+    /// Cells as (row, col) in the field, sorted.
+    pub cells: Vec<(usize, usize)>,
+}
+
+/// Cells of `kind` around its center, as (right, up), in fumen's spawn
+/// orientation turned `rotation` times clockwise.
+fn blocks(kind: char, rotation: usize) -> Vec<(isize, isize)> {
+    let spawn: [(isize, isize); 4] = match kind {
+        'I' => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        'L' => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        'O' => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        'Z' => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        'T' => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        'J' => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        'S' => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+        _ => panic!("Unknown piece {kind}"),
+    };
+    spawn.iter().map(|&(mut x, mut y)| {
+        for _ in 0..rotation {
+            (x, y) = (y, -x);
+        }
+        (x, y)
+    }).collect()
+}
+
+/// Shift from the position stored in a fumen to the center `blocks` uses,
+/// which differ for some orientations of the O, I, S and Z pieces.
+fn center_shift(kind: char, rotation: usize) -> (isize, isize) {
+    match (kind, rotation) {
+        ('O', 0) | ('S', 0) | ('Z', 0) => (0, -1),
+        ('O', 2) | ('I', 2) | ('Z', 3) => (1, 0),
+        ('O', 3) => (1, -1),
+        ('I', 3) => (0, -1),
+        ('S', 1) => (-1, 0),
+        _ => (0, 0),
+    }
+}
+
+/// Rotation codes of the spawn, right, reverse and left orientations. The
+/// mapping is its own inverse.
+const ROTATION_CODES: [usize; 4] = [2, 1, 0, 3];
+
+impl Piece {
+    fn position(&self) -> usize {
+        let shape = blocks(self.kind, self.rotation);
+        let cells: Vec<(isize, isize)> = self.cells.iter()
+            .map(|&(row, col)| (col as isize, (HEIGHT - 1) as isize - row as isize))
+            .collect();
+        let min = |points: &[(isize, isize)], axis: fn(&(isize, isize)) -> isize| {
+            points.iter().map(axis).min().unwrap_or(0)
+        };
+        let (dx, dy) = center_shift(self.kind, self.rotation);
+        let x = min(&cells, |c| c.0) - min(&shape, |c| c.0) - dx;
+        let y = min(&cells, |c| c.1) - min(&shape, |c| c.1) - dy;
+        ((HEIGHT as isize - 1 - y)*WIDTH as isize + x) as usize
+    }
+
+    fn from_position(kind: char, rotation: usize, position: usize) -> Option<Self> {
+        let (dx, dy) = center_shift(kind, rotation);
+        let x = (position % WIDTH) as isize + dx;
+        let y = (HEIGHT - 1) as isize - (position / WIDTH) as isize + dy;
+        let mut cells = Vec::new();
+        for (bx, by) in blocks(kind, rotation) {
+            let (col, row) = (x + bx, (HEIGHT - 1) as isize - (y + by));
+            if !(0..WIDTH as isize).contains(&col) || !(0..HEIGHT as isize).contains(&row) {
+                return None;
+            }
+            cells.push((row as usize, col as usize));
+        }
+        cells.sort();
+        Some(Piece { kind, rotation, cells })
+    }
+
+    fn value(&self) -> u8 {
+        PIECES.iter().position(|&c| c == self.kind).unwrap() as u8 + 1
+    }
+}
+
+impl Page {
+    /// The stack on this page as a 10-wide board of at least `height` rows,
+    /// ignoring the garbage row.
+    pub fn stack(&self, height: usize) -> Bitmap2D {
+        let top = (0..HEIGHT*WIDTH).find(|&idx| self.field[idx] != 0)
+            .map_or(HEIGHT, |idx| idx / WIDTH);
+        let rows = height.max(HEIGHT - top).min(HEIGHT);
+        let mut board = Bitmap2D::zeros((rows, WIDTH));
+        for row in 0..rows {
+            for col in 0..WIDTH {
+                if self.field[(HEIGHT - rows + row)*WIDTH + col] != 0 {
+                    board.set((row, col));
+                }
+            }
+        }
+        board
+    }
+
+    /// The hold piece and queue in the comment, either in quiz form
+    /// (`#Q=[HOLD](CURRENT)NEXT`) or as a bare list of pieces.
+    pub fn queue(&self) -> Option<(Option<char>, Vec<char>)> {
+        let pieces = |text: &str| text.chars()
+            .map(|c| PIECES.contains(&c).then_some(c))
+            .collect::<Option<Vec<char>>>();
+        let comment = self.comment.trim();
+        match comment.strip_prefix("#Q=[") {
+            Some(rest) => {
+                let (hold, rest) = rest.split_once("](")?;
+                let (current, next) = rest.split_once(')')?;
+                let hold = pieces(hold)?;
+                if hold.len() > 1 { return None }
+                let queue = pieces(&format!("{current}{next}"))?;
+                Some((hold.first().copied(), queue))
+            },
+            None => pieces(comment).filter(|queue| !queue.is_empty()).map(|queue| (None, queue)),
+        }
+    }
+}
+
+/// Pages dropping `placements` one by one onto `board`, which sits in the
+/// bottom-left corner of the field with garbage to its right. Placements
+/// are in the coordinates of the board as the previous ones left it, after
+/// clearing full rows if `clear_lines` is set.
+pub fn pages(board: &Bitmap2D, placements: &[&Placement], clear_lines: bool)
+               -> Result<Vec<Page>, TalosError> {
+    let (rows, cols) = board.shape;
+    if rows > HEIGHT || cols > WIDTH {
+        return Err(TalosError::InvalidBoard(format!(
+            "{rows}x{cols} does not fit in a fumen field ({HEIGHT} rows of {WIDTH})")));
+    }
+    let mut grid: Vec<Vec<u8>> = (0..rows)
+        .map(|row| (0..cols)
+             .map(|col| if board.get((row, col)).unwrap_or(false) { GARBAGE } else { 0 })
+             .collect())
+        .collect();
+    let top = HEIGHT - rows;
+    let mut out = Vec::with_capacity(placements.len());
+    for placement in placements {
+        let mut field = [0; BLOCKS];
+        for row in 0..rows {
+            for col in 0..WIDTH {
+                field[(top + row)*WIDTH + col] = grid[row].get(col).copied().unwrap_or(GARBAGE);
+            }
+        }
+        let cells = placement.bitmap.ones();
+        let piece = Piece { kind: placement.piece, rotation: placement.rotation,
+                            cells: cells.iter().map(|&(row, col)| (top + row, col)).collect() };
+        for &(row, col) in &cells {
+            grid[row][col] = piece.value();
+        }
+        if clear_lines {
+            grid.retain(|row| row.contains(&0));
+            while grid.len() < rows {
+                grid.insert(0, vec![0; cols]);
+            }
+        }
+        out.push(Page { field, piece: Some(piece), comment: String::new() });
+    }
+    Ok(out)
+}
+
+/// Base-64 digits, least significant first.
+fn push(out: &mut Vec<u8>, mut value: usize, digits: usize) {
+    for _ in 0..digits {
+        out.push((value % 64) as u8);
+        value /= 64;
+    }
+}
+
+/// Escapes `text` like JavaScript's `escape`, as fumen does before storing
+/// comments.
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '@' | '*' | '_' | '+' | '-' | '.' | '/' =>
+                out.push(c),
+            c if (c as u32) < 256 => out.push_str(&format!("%{:02X}", c as u32)),
+            c => for unit in c.encode_utf16(&mut [0; 2]) {
+                out.push_str(&format!("%u{unit:04X}"));
+            },
+        }
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let hex = |digits: &str| u16::from_str_radix(digits, 16).ok();
+        let (unit, len) = match (rest.get(1..2), rest.get(2..6), rest.get(1..3)) {
+            _ if c != '%' => (c as u16, 1),
+            (Some("u"), Some(digits), _) if hex(digits).is_some() => (hex(digits).unwrap(), 6),
+            (_, _, Some(digits)) if hex(digits).is_some() => (hex(digits).unwrap(), 3),
+            _ => (c as u16, 1),
+        };
+        units.push(unit);
+        rest = &rest[len..];
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// Puts `piece` into the field and clears the rows it fills.
+fn lock(field: &mut [u8; BLOCKS], piece: &Piece) {
+    for &(row, col) in &piece.cells {
+        field[row*WIDTH + col] = piece.value();
+    }
+    clear_lines(field);
+}
+
+/// Full rows of the visible field are removed as in the game, leaving the
+/// garbage row alone.
+fn clear_lines(field: &mut [u8; BLOCKS]) {
+    let kept: Vec<usize> = (0..HEIGHT)
+        .filter(|&row| field[row*WIDTH..(row + 1)*WIDTH].contains(&0))
+        .collect();
+    let mut cleared = [0; BLOCKS];
+    let top = HEIGHT - kept.len();
+    for (idx, &row) in kept.iter().enumerate() {
+        cleared[(top + idx)*WIDTH..(top + idx + 1)*WIDTH]
+            .copy_from_slice(&field[row*WIDTH..(row + 1)*WIDTH]);
+    }
+    cleared[HEIGHT*WIDTH..].copy_from_slice(&field[HEIGHT*WIDTH..]);
+    *field = cleared;
+}
+
+/// Encodes `pages` as a v115 fumen. Each field is stored as a difference
+/// with the previous field after its piece locked.
+pub fn encode(pages: &[Page]) -> String {
+    let mut out = Vec::new();
+    let mut prev = [0; BLOCKS];
+    let mut prev_comment = "";
+    // Digit counting the following pages with the same field.
+    let mut repeat: Option<usize> = None;
+    for page in pages {
+        if page.field == prev {
+            match repeat {
+                Some(at) if out[at] < 63 => out[at] += 1,
+                _ => {
+                    push(&mut out, 8*BLOCKS + BLOCKS - 1, 2);
+                    out.push(0);
+                    repeat = Some(out.len() - 1);
+                },
+            }
+        } else {
+            repeat = None;
+            let diffs: Vec<usize> = page.field.iter().zip(&prev)
+                .map(|(&cur, &old)| cur as usize + 8 - old as usize)
+                .collect();
+            for run in diffs.chunk_by(|a, b| a == b) {
+                push(&mut out, run[0]*BLOCKS + run.len() - 1, 2);
+            }
+        }
+
+        let has_comment = page.comment != prev_comment;
+        let (kind, rotation, position) = match &page.piece {
+            Some(piece) => (piece.value() as usize, ROTATION_CODES[piece.rotation], piece.position()),
+            None => (0, 0, 0),
+        };
+        // The editor saves every page with the colorize flag.
+        let flags = usize::from(has_comment)*2 + 1;
+        push(&mut out, ((flags*4*BLOCKS + position)*4 + rotation)*8 + kind, 3);
+        if has_comment {
+            let mut escaped = escape(&page.comment);
+            escaped.truncate(MAX_COMMENT);
+            push(&mut out, escaped.len(), 2);
+            for chunk in escaped.as_bytes().chunks(4) {
+                let value = chunk.iter().rev()
+                    .fold(0, |acc, &c| acc*COMMENT_BASE + (c - b' ') as u32);
+                push(&mut out, value as usize, 5);
+            }
+            prev_comment = &page.comment;
+        }
+
+        prev = page.field;
+        match &page.piece {
+            Some(piece) => lock(&mut prev, piece),
+            None => clear_lines(&mut prev),
+        }
+    }
+    let data: String = out.iter().map(|&digit| TABLE[digit as usize] as char).collect();
+    format!("v115@{data}")
+}
+
+/// Reads base-64 digits, skipping the '?' that split long fumens in URLs.
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, at: usize, message: impl std::fmt::Display) -> TalosError {
+        TalosError::parse_at(self.text, self.text[..at.min(self.text.len())].chars().count(),
+                             message)
+    }
+
+    fn poll(&mut self, digits: usize) -> Result<usize, TalosError> {
+        let mut value = 0;
+        let mut scale = 1;
+        for _ in 0..digits {
+            while self.text.as_bytes().get(self.pos) == Some(&b'?') {
+                self.pos += 1;
+            }
+            let Some(&byte) = self.text.as_bytes().get(self.pos) else {
+                return Err(self.error(self.pos, "Fumen ends too early"));
+            };
+            let Some(digit) = TABLE.iter().position(|&c| c == byte) else {
+                let c = self.text[self.pos..].chars().next().unwrap();
+                return Err(self.error(self.pos, format!("Unexpected character '{c}' in fumen")));
+            };
+            value += digit*scale;
+            scale *= 64;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Decodes the first page of a v115 fumen, with or without the URL around it.
+pub fn decode(text: &str) -> Result<Page, TalosError> {
+    Ok(decode_pages(text)?.swap_remove(0))
+}
+
+/// Decodes every page of a v115 fumen, with or without the URL around it.
+pub fn decode_pages(text: &str) -> Result<Vec<Page>, TalosError> {
+    let text = text.trim();
+    let Some(at) = text.find('@') else {
+        return Err(TalosError::parse_at(text, 0, "Not a fumen, expected v115@..."));
+    };
+    if !["v115", "m115", "d115"].iter().any(|version| text[..at].ends_with(version)) {
+        return Err(TalosError::parse_at(text, 0, "Unsupported fumen version, expected v115"));
+    }
+    let mut reader = Reader { text, pos: at + 1 };
+    let mut pages = Vec::new();
+    let mut prev = [0; BLOCKS];
+    let mut comment = String::new();
+    // Pages left that keep the previous field.
+    let mut repeat = 0;
+
+    loop {
+        let mut field = prev;
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut filled = 0;
+            while filled < BLOCKS {
+                let start = reader.pos;
+                let value = reader.poll(2)?;
+                let (diff, len) = (value / BLOCKS, value % BLOCKS + 1);
+                if diff > 16 || filled + len > BLOCKS {
+                    return Err(reader.error(start, "Invalid field in fumen"));
+                }
+                for cell in &mut field[filled..filled + len] {
+                    *cell = match (*cell + diff as u8).checked_sub(8) {
+                        Some(value) if value <= GARBAGE => value,
+                        _ => return Err(reader.error(start, "Invalid field in fumen")),
+                    };
+                }
+                if filled == 0 && len == BLOCKS && diff == 8 {
+                    repeat = reader.poll(1)?;
+                }
+                filled += len;
+            }
+        }
+
+        let start = reader.pos;
+        let action = reader.poll(3)?;
+        let (kind, rotation) = (action % 8, ROTATION_CODES[action / 8 % 4]);
+        let (position, flags) = (action / 32 % BLOCKS, action / 32 / BLOCKS);
+        let piece = match kind {
+            0 => None,
+            _ => Some(Piece::from_position(PIECES[kind - 1], rotation, position)
+                      .ok_or_else(|| reader.error(start, "Piece outside the field in fumen"))?),
+        };
+        if flags & 8 != 0 {
+            let len = reader.poll(2)?;
+            let mut escaped = String::with_capacity(len);
+            for chunk in (0..len).step_by(4) {
+                let mut value = reader.poll(5)?;
+                for _ in chunk..(chunk + 4).min(len) {
+                    escaped.push((b' ' + (value % COMMENT_BASE as usize) as u8) as char);
+                    value /= COMMENT_BASE as usize;
+                }
+            }
+            comment = unescape(&escaped);
+        }
+
+        prev = field;
+        // Unless the lock flag is off, the piece locks and full rows clear.
+        if flags & 16 == 0 {
+            match &piece {
+                Some(piece) => lock(&mut prev, piece),
+                None => clear_lines(&mut prev),
+            }
+            if flags & 1 != 0 {
+                prev.copy_within(WIDTH.., 0);
+                prev[HEIGHT*WIDTH..].fill(0);
+            }
+            if flags & 2 != 0 {
+                for row in prev[..HEIGHT*WIDTH].chunks_mut(WIDTH) {
+                    row.reverse();
+                }
+            }
+        }
+        pages.push(Page { field, piece, comment: comment.clone() });
+        if reader.pos >= text.len() || text[reader.pos..].bytes().all(|b| b == b'?') {
+            return Ok(pages);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_padded_pieces;
+
+    #[test]
+    fn empty_page() {
+        let page = Page { field: [0; BLOCKS], piece: None, comment: String::new() };
+        assert_eq!(encode(&[page]), "v115@vhAAgH");
+        let page = decode("https://fumen.zui.jp/?v115@vhAAgH").unwrap();
+        assert!(page.field.iter().all(|&cell| cell == 0) && page.piece.is_none());
+        assert!(decode("v110@vhAAgH").is_err());
+        assert!(decode("v115@vh").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut board = Bitmap2D::zeros((2, 10));
+        for col in 0..8 { board.set((1, col)) }
+        let tables = get_padded_pieces(board.shape);
+        let o = &tables[6].get(&(0, 8)).unwrap()[0];
+        let pages = pages(&board, &[o], true).unwrap();
+        let mut first = pages.into_iter().next().unwrap();
+        first.comment = "#Q=[T](O)IJ".to_string();
+        let page = decode(&encode(&[first])).unwrap();
+        assert_eq!(page.stack(2).to_string(), board.to_string());
+        assert_eq!(page.piece.as_ref().unwrap().cells, vec![(21, 8), (21, 9), (22, 8), (22, 9)]);
+        assert_eq!(page.queue(), Some((Some('T'), vec!['O', 'I', 'J'])));
+
+        // Every orientation of every piece keeps its cells.
+        for kind in PIECES {
+            for rotation in 0..4 {
+                let shape = blocks(kind, rotation);
+                let cells: Vec<(usize, usize)> = shape.iter()
+                    .map(|&(x, y)| ((10 - y) as usize, (4 + x) as usize))
+                    .collect();
+                let mut sorted = cells.clone();
+                sorted.sort();
+                let piece = Piece { kind, rotation, cells: sorted };
+                let decoded = Piece::from_position(kind, rotation, piece.position()).unwrap();
+                assert_eq!(decoded, piece);
+            }
+        }
+        assert_eq!(unescape(&escape("#Q=[](T) é")), "#Q=[](T) é");
+    }
+
+    #[test]
+    fn published_fumen() {
+        // The first six pages of the example in the tetris-fumen README,
+        // saved by the fumen editor: an opening with a comment on the first
+        // page, which the following pages keep.
+        let text = "v115@vhGRQYHAvItJEJmhCAUGJKJJvMJTNJGBJ";
+        let pages = decode_pages(text).unwrap();
+        let pieces: Vec<_> = pages.iter()
+            .map(|page| page.piece.as_ref().unwrap())
+            .map(|piece| (piece.kind, piece.rotation, piece.cells.clone()))
+            .collect();
+        assert_eq!(pieces, [('I', 0, vec![(22, 3), (22, 4), (22, 5), (22, 6)]),
+                            ('Z', 0, vec![(20, 3), (20, 4), (21, 4), (21, 5)]),
+                            ('L', 1, vec![(20, 0), (21, 0), (22, 0), (22, 1)]),
+                            ('S', 1, vec![(20, 6), (21, 6), (21, 7), (22, 7)]),
+                            ('O', 0, vec![(21, 8), (21, 9), (22, 8), (22, 9)]),
+                            ('J', 2, vec![(19, 3), (19, 4), (19, 5), (20, 5)])]);
+        assert!(pages.iter().all(|page| page.comment == "Opening"));
+        assert_eq!(pages[5].stack(3).to_string(), "1001101000\n1000111111\n1101111111");
+        // The seventh page is left out, so one page fewer repeats the empty
+        // field.
+        assert_eq!(encode(&pages), text.replace("vhG", "vhF"));
+    }
+}
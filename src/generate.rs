@@ -0,0 +1,184 @@
+use crate::bitmap::Bitmap2D;
+use crate::piece::{Placement, PaddedPieces, PIECE_ORDER};
+use crate::rng::Rng;
+use crate::solver::{increment, Solver, TimedOut};
+use std::collections::HashSet;
+use std::ops::ControlFlow;
+use std::time::Instant;
+
+/// Tiles `board` with any mix of the tetrominoes marked in `allowed`,
+/// trying the placements at each empty cell in random order, until
+/// `deadline` if any.
+pub fn random_tiling<'a>(board: &Bitmap2D, position: (usize, usize),
+                         padded_pieces: &'a PaddedPieces, allowed: [bool; 7], rng: &mut Rng,
+                         deadline: Option<Instant>)
+                           -> Result<Option<Vec<&'a Placement>>, TimedOut> {
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return Err(TimedOut);
+    }
+    if board.get(position).unwrap_or(false) {
+        return match increment(board.shape, position) {
+            Some(next_pos) => random_tiling(board, next_pos, padded_pieces, allowed, rng, deadline),
+            None => Ok(Some(vec![]))
+        };
+    }
+    let mut candidates: Vec<&Placement> = padded_pieces.iter().zip(allowed)
+        .filter(|(_, allowed)| *allowed)
+        .filter_map(|(piece_dict, _)| piece_dict.get(&position))
+        .flatten()
+        .filter(|variant| !board.intersects(&variant.bitmap))
+        .collect();
+    rng.shuffle(&mut candidates);
+    for variant in candidates {
+        let new_board = board.or(&variant.bitmap);
+        let solution = match increment(board.shape, position) {
+            Some(next_pos) => random_tiling(&new_board, next_pos, padded_pieces, allowed, rng,
+                                            deadline)?,
+            None => Some(vec![])
+        };
+        if let Some(mut solution) = solution {
+            solution.push(variant);
+            return Ok(Some(solution));
+        }
+    }
+    Ok(None)
+}
+
+/// Piece counts of a solution, indexed like `PIECE_ORDER`.
+pub fn piece_counts(solution: &[&Placement]) -> [u32; 7] {
+    let mut counts = [0; 7];
+    for placement in solution {
+        let piece_id = crate::piece::PIECE_ORDER.iter()
+            .position(|&c| c == placement.piece).unwrap();
+        counts[piece_id] += 1;
+    }
+    counts
+}
+
+/// A tiling as its pieces with their cells, sorted so that equal tilings
+/// compare equal.
+type Cells = Vec<(char, Vec<(usize, usize)>)>;
+
+fn cells(solution: &[&Placement]) -> Cells {
+    let mut out: Cells = solution.iter().map(|p| (p.piece, p.bitmap.ones())).collect();
+    out.sort();
+    out
+}
+
+/// Mirror image of a piece type.
+fn mirrored(piece: char) -> char {
+    match piece {
+        'J' => 'L',
+        'L' => 'J',
+        'S' => 'Z',
+        'Z' => 'S',
+        _ => piece,
+    }
+}
+
+/// Maps a cell of a `shape` board through one of its 8 possible
+/// symmetries, along with whether that symmetry is a reflection. The last 4
+/// swap rows and columns and only apply to square boards.
+fn transform(symmetry: usize, (r, c): (usize, usize), (rows, cols): (usize, usize))
+             -> ((usize, usize), bool) {
+    match symmetry {
+        0 => ((r, c), false),
+        1 => ((rows - 1 - r, cols - 1 - c), false),
+        2 => ((r, cols - 1 - c), true),
+        3 => ((rows - 1 - r, c), true),
+        4 => ((c, r), true),
+        5 => ((cols - 1 - c, rows - 1 - r), true),
+        6 => ((c, rows - 1 - r), false),
+        _ => ((cols - 1 - c, r), false),
+    }
+}
+
+/// Images of `tiling` under the symmetries of a `shape` board that keep
+/// the piece counts: reflections swap J with L and S with Z, so they only
+/// count when there are as many of each.
+fn images(tiling: &Cells, shape: (usize, usize), counts: &[u32; 7]) -> HashSet<Cells> {
+    let count = |piece: char| counts[PIECE_ORDER.iter().position(|&c| c == piece).unwrap()];
+    let mirrors = count('J') == count('L') && count('S') == count('Z');
+    let symmetries = if shape.0 == shape.1 { 8 } else { 4 };
+    (0..symmetries).filter(|&symmetry| mirrors || !transform(symmetry, (0, 0), shape).1)
+        .map(|symmetry| {
+            let mut image: Cells = tiling.iter().map(|(piece, cells)| {
+                let mut cells: Vec<(usize, usize)> = cells.iter()
+                    .map(|&cell| transform(symmetry, cell, shape).0)
+                    .collect();
+                cells.sort();
+                let mirror = transform(symmetry, (0, 0), shape).1;
+                (if mirror { mirrored(*piece) } else { *piece }, cells)
+            }).collect();
+            image.sort();
+            image
+        })
+        .collect()
+}
+
+/// Whether every tiling of `board` with `counts` is a symmetric image of
+/// the first one the solver finds, which it returns with the nodes visited
+/// until then. `None` when there is no tiling.
+pub fn unique_solution<'a>(solver: &mut Solver<'a>, board: &Bitmap2D, counts: [u32; 7])
+                             -> Option<(Vec<&'a Placement>, u64, bool)> {
+    let first = solver.first(board, counts)?;
+    let nodes = solver.nodes;
+    let known = images(&cells(&first), board.shape, &counts);
+    let mut unique = true;
+    solver.for_each(board, counts, |sol| {
+        if known.contains(&cells(sol)) { return ControlFlow::Continue(()) }
+        unique = false;
+        ControlFlow::Break(())
+    });
+    Some((first, nodes, unique))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_padded_pieces;
+    use crate::solver::fill_board;
+
+    #[test]
+    fn random_tilings_are_solvable() {
+        let board = Bitmap2D::zeros((6, 8));
+        let pieces = get_padded_pieces(board.shape);
+        for seed in 0..5 {
+            let tiling = random_tiling(&board, (0, 0), &pieces, [true; 7], &mut Rng::new(seed), None)
+                .unwrap().unwrap();
+            assert_eq!(tiling.len(), 12);
+            let counts = piece_counts(&tiling);
+            assert_eq!(counts.iter().sum::<u32>(), 12);
+            assert!(fill_board(&board, counts, (0, 0), &pieces).is_some());
+        }
+        let board = Bitmap2D::zeros((3, 3));
+        let pieces = get_padded_pieces(board.shape);
+        assert!(random_tiling(&board, (0, 0), &pieces, [true; 7], &mut Rng::new(0), None)
+                .unwrap().is_none());
+        assert!(random_tiling(&board, (0, 0), &pieces, [true; 7], &mut Rng::new(0),
+                              Some(Instant::now())).is_err());
+    }
+
+    #[test]
+    fn uniqueness() {
+        // Two upright I pieces side by side.
+        let board = Bitmap2D::zeros((4, 2));
+        let pieces = get_padded_pieces(board.shape);
+        let mut solver = Solver::new(&pieces);
+        let (_, _, unique) = unique_solution(&mut solver, &board, [0, 2, 0, 0, 0, 0, 0]).unwrap();
+        assert!(unique);
+
+        // Four I pieces tile a square all flat or all upright, which are
+        // rotations of each other. Two I pieces can also sandwich two O
+        // pieces, which no symmetry turns into the other tilings.
+        let board = Bitmap2D::zeros((4, 4));
+        let pieces = get_padded_pieces(board.shape);
+        let mut solver = Solver::new(&pieces);
+        let (_, _, unique) = unique_solution(&mut solver, &board, [0, 4, 0, 0, 0, 0, 0]).unwrap();
+        assert!(unique);
+        let mut solver = Solver::new(&pieces);
+        let (_, _, unique) = unique_solution(&mut solver, &board, [0, 2, 0, 0, 0, 0, 2]).unwrap();
+        assert!(!unique);
+        assert!(unique_solution(&mut solver, &board, [0, 0, 0, 0, 4, 0, 0]).is_none());
+    }
+}
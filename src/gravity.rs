@@ -0,0 +1,209 @@
+use crate::bitmap::Bitmap2D;
+use crate::piece::Placement;
+use crate::srs;
+use crate::tetris;
+use std::collections::{HashSet, VecDeque};
+
+/// How a piece may move on its way down. Row 0 is the top of the board.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DropMode {
+    /// Straight down from above its final columns.
+    Straight,
+    /// Down, left or right, as with the arrow keys, without rotating.
+    Shift,
+    /// Shifts plus rotations with the wall kicks of the Super Rotation
+    /// System, starting from the spawn orientation.
+    Srs,
+}
+
+impl std::str::FromStr for DropMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "straight" => Ok(DropMode::Straight),
+            "shift" => Ok(DropMode::Shift),
+            "srs" => Ok(DropMode::Srs),
+            _ => Err(format!("Unrecognized drop mode: {s} (expected straight, shift or srs)"))
+        }
+    }
+}
+
+/// Whether `placement` can fall from above the board into its position
+/// without crossing `occupied` cells, and rest there.
+pub fn can_drop(placement: &Placement, occupied: &Bitmap2D, mode: DropMode) -> bool {
+    if mode == DropMode::Srs {
+        return srs::can_reach(placement, occupied);
+    }
+    let (rows, cols) = occupied.shape;
+    let cells = placement.bitmap.ones();
+    let top = cells.iter().map(|c| c.0).min().unwrap_or(0);
+    let left = cells.iter().map(|c| c.1).min().unwrap_or(0);
+    let shape: Vec<(isize, isize)> = cells.iter()
+        .map(|&(row, col)| ((row - top) as isize, (col - left) as isize))
+        .collect();
+    let height = shape.iter().map(|c| c.0).max().unwrap_or(0) + 1;
+    let width = shape.iter().map(|c| c.1).max().unwrap_or(0) + 1;
+    let free = |(row, col): (isize, isize)| shape.iter().all(|&(drow, dcol)| {
+        let (row, col) = (row + drow, col + dcol);
+        col >= 0 && col < cols as isize && row < rows as isize
+            && (row < 0 || !occupied.get((row as usize, col as usize)).unwrap_or(true))
+    });
+
+    let target = (top as isize, left as isize);
+    if !free(target) || free((target.0 + 1, target.1)) {
+        return false;
+    }
+    let starts: Vec<isize> = match mode {
+        DropMode::Straight => vec![target.1],
+        DropMode::Shift | DropMode::Srs => (0..=cols as isize - width).collect(),
+    };
+    let mut seen: HashSet<(isize, isize)> = starts.iter().map(|&col| (-height, col)).collect();
+    let mut queue: VecDeque<(isize, isize)> = seen.iter().copied().collect();
+    while let Some((row, col)) = queue.pop_front() {
+        if (row, col) == target { return true }
+        let mut moves = vec![(row + 1, col)];
+        if mode == DropMode::Shift {
+            moves.extend([(row, col - 1), (row, col + 1)]);
+        }
+        for next in moves {
+            if next.0 <= target.0 && free(next) && seen.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    false
+}
+
+/// Order in which `placements` can be dropped one after the other onto
+/// `board`, as indices into `placements`.
+pub fn drop_order(board: &Bitmap2D, placements: &[&Placement], mode: DropMode)
+                    -> Option<Vec<usize>> {
+    // Lowest pieces first, as they usually have to go in first.
+    let mut candidates: Vec<usize> = (0..placements.len()).collect();
+    candidates.sort_by_key(|&idx| {
+        std::cmp::Reverse(placements[idx].bitmap.ones().iter().map(|c| c.0).max())
+    });
+    let mut order = Vec::with_capacity(placements.len());
+    let mut dead_ends = HashSet::new();
+    search(board, placements, mode, &candidates, &mut vec![false; placements.len()],
+           &mut order, &mut dead_ends).then_some(order)
+}
+
+fn search(board: &Bitmap2D, placements: &[&Placement], mode: DropMode, candidates: &[usize],
+          dropped: &mut Vec<bool>, order: &mut Vec<usize>,
+          dead_ends: &mut HashSet<Vec<bool>>) -> bool {
+    if order.len() == placements.len() { return true }
+    if dead_ends.contains(dropped) { return false }
+    for &idx in candidates {
+        if dropped[idx] || !can_drop(placements[idx], board, mode) { continue }
+        dropped[idx] = true;
+        order.push(idx);
+        if search(&board.or(&placements[idx].bitmap), placements, mode, candidates,
+                  dropped, order, dead_ends) {
+            return true;
+        }
+        order.pop();
+        dropped[idx] = false;
+    }
+    dead_ends.insert(dropped.clone());
+    false
+}
+
+/// Order in which `placements` can be dropped onto `board` when pieces come
+/// from `queue`, with a hold slot if `use_hold` is set. `queue` holds
+/// exactly the pieces of `placements`.
+pub fn queue_order(board: &Bitmap2D, placements: &[&Placement], queue: &[char], use_hold: bool,
+                   mode: DropMode) -> Option<Vec<usize>> {
+    let mut order = Vec::with_capacity(placements.len());
+    let mut search = QueueSearch { placements, queue, use_hold, mode, dead_ends: HashSet::new() };
+    search.run(board, &mut vec![false; placements.len()], 0, None, &mut order)
+        .then_some(order)
+}
+
+struct QueueSearch<'a> {
+    placements: &'a [&'a Placement],
+    queue: &'a [char],
+    use_hold: bool,
+    mode: DropMode,
+    dead_ends: HashSet<(Vec<bool>, usize, Option<char>)>,
+}
+
+impl QueueSearch<'_> {
+    fn run(&mut self, board: &Bitmap2D, dropped: &mut Vec<bool>, next: usize,
+           hold: Option<char>, order: &mut Vec<usize>) -> bool {
+        if order.len() == self.placements.len() { return true }
+        let key = (dropped.clone(), next, hold);
+        if self.dead_ends.contains(&key) { return false }
+        for choice in tetris::choices(self.queue, next, hold, self.use_hold) {
+            for idx in 0..self.placements.len() {
+                let placement = self.placements[idx];
+                if dropped[idx] || placement.piece != choice.piece
+                    || !can_drop(placement, board, self.mode) { continue }
+                dropped[idx] = true;
+                order.push(idx);
+                if self.run(&board.or(&placement.bitmap), dropped, choice.next, choice.hold,
+                            order) {
+                    return true;
+                }
+                order.pop();
+                dropped[idx] = false;
+            }
+        }
+        self.dead_ends.insert(key);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_padded_pieces;
+
+    #[test]
+    fn drops() {
+        let mut board = Bitmap2D::zeros((2, 4));
+        let pieces = get_padded_pieces(board.shape);
+        let i_top = &pieces[1].get(&(0, 0)).unwrap()[0];
+        let i_bottom = &pieces[1].get(&(1, 0)).unwrap()[0];
+        assert!(!can_drop(i_top, &board, DropMode::Shift));
+        assert!(can_drop(i_bottom, &board, DropMode::Straight));
+
+        // Flat L under a block: it has to fall one column right of its
+        // target, then slide left.
+        board.set((0, 0));
+        let l_flat = pieces[2].get(&(0, 2)).unwrap().iter().find(|p| p.variant == 2).unwrap();
+        assert!(!can_drop(l_flat, &board, DropMode::Straight));
+        assert!(can_drop(l_flat, &board, DropMode::Shift));
+    }
+
+    #[test]
+    fn orders() {
+        let board = Bitmap2D::zeros((2, 4));
+        let pieces = get_padded_pieces(board.shape);
+        let i_top = &pieces[1].get(&(0, 0)).unwrap()[0];
+        let i_bottom = &pieces[1].get(&(1, 0)).unwrap()[0];
+        assert_eq!(drop_order(&board, &[i_top, i_bottom], DropMode::Straight), Some(vec![1, 0]));
+
+        // Nothing holds an upside-down T on an empty board.
+        let board = Bitmap2D::zeros((3, 3));
+        let pieces = get_padded_pieces(board.shape);
+        let t_up = pieces[3].get(&(0, 0)).unwrap().iter().find(|p| p.variant == 3).unwrap();
+        assert!(drop_order(&board, &[t_up], DropMode::Shift).is_none());
+    }
+
+    #[test]
+    fn queue_orders() {
+        // The I piece has to go in first, under the O.
+        let board = Bitmap2D::zeros((3, 4));
+        let pieces = get_padded_pieces(board.shape);
+        let i_bottom = &pieces[1].get(&(2, 0)).unwrap()[0];
+        let o_top = &pieces[6].get(&(0, 1)).unwrap()[0];
+        let placements = [o_top, i_bottom];
+        assert!(queue_order(&board, &placements, &['O', 'I'], false, DropMode::Shift).is_none());
+        assert_eq!(queue_order(&board, &placements, &['I', 'O'], false, DropMode::Shift),
+                   Some(vec![1, 0]));
+        assert_eq!(queue_order(&board, &placements, &['O', 'I'], true, DropMode::Shift),
+                   Some(vec![1, 0]));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::piece::Placement;
+use std::fmt;
+
+/// Minimal JSON value, enough to serialize solver output without pulling
+/// in external crates.
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Self {
+        Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self { Json::Bool(value) }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self { Json::Int(value as i64) }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self { Json::Float(value) }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self { Json::Str(value.to_string()) }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self { Json::Str(value) }
+}
+
+impl From<char> for Json {
+    fn from(value: char) -> Self { Json::Str(value.to_string()) }
+}
+
+impl From<(usize, usize)> for Json {
+    fn from(value: (usize, usize)) -> Self {
+        Json::Array(vec![value.0.into(), value.1.into()])
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Int(i) => write!(f, "{i}"),
+            Json::Float(x) if x.is_finite() => write!(f, "{x}"),
+            Json::Float(_) => f.write_str("null"),
+            Json::Str(s) => write_escaped(f, s),
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 { f.write_str(",")?; }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            },
+            Json::Object(fields) => {
+                f.write_str("{")?;
+                for (idx, (key, value)) in fields.iter().enumerate() {
+                    if idx > 0 { f.write_str(",")?; }
+                    write_escaped(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            },
+        }
+    }
+}
+
+/// `rotation` is the variant index, as it has been since the first version
+/// of the format. `spawn_rotation` counts clockwise quarter turns from the
+/// spawn orientation, like the instructions output.
+pub fn placement(placement: &Placement) -> Json {
+    Json::object(vec![
+        ("piece", placement.piece.into()),
+        ("rotation", placement.variant.into()),
+        ("spawn_rotation", placement.rotation.into()),
+        ("origin", placement.origin.into()),
+        ("cells", Json::Array(placement.bitmap.ones().into_iter()
+                                  .map(Json::from).collect())),
+    ])
+}
+
+pub fn solution(placements: &[&Placement]) -> Json {
+    Json::Array(placements.iter().map(|p| placement(p)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_padded_pieces;
+
+    #[test]
+    fn serialize() {
+        let value = Json::object(vec![
+            ("name", "a \"quoted\"\nline".into()),
+            ("values", Json::Array(vec![1usize.into(), Json::Null, true.into()])),
+            ("ratio", 0.5.into()),
+        ]);
+        assert_eq!(value.to_string(),
+                   r#"{"name":"a \"quoted\"\nline","values":[1,null,true],"ratio":0.5}"#);
+    }
+
+    #[test]
+    fn serialize_placement() {
+        let pieces = get_padded_pieces((4, 4));
+        let o_piece = &pieces[6].get(&(1, 2)).unwrap()[0];
+        assert_eq!(placement(o_piece).to_string(),
+                   r#"{"piece":"O","rotation":0,"spawn_rotation":0,"origin":[1,2],"cells":[[1,2],[1,3],[2,2],[2,3]]}"#);
+        let j_piece = &pieces[0].get(&(0, 1)).unwrap()[0];
+        assert_eq!(placement(j_piece).to_string(),
+                   r#"{"piece":"J","rotation":0,"spawn_rotation":3,"origin":[0,1],"cells":[[0,1],[1,1],[2,0],[2,1]]}"#);
+    }
+}
@@ -0,0 +1,97 @@
+use std::env;
+
+/// Columns used when the terminal width is unknown.
+pub const DEFAULT_WIDTH: usize = 80;
+
+/// Terminal width from `COLUMNS`, as exported by most shells.
+pub fn terminal_width() -> usize {
+    env::var("COLUMNS").ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+/// Number of columns a line takes on screen, skipping ANSI escape sequences.
+pub fn visible_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // CSI sequences end with a letter, e.g. "\x1b[48;5;214m".
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() { break }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+/// Lays out rendered solutions side by side, as many per row as fit in
+/// `width` columns, each headed by its number.
+pub fn pack(blocks: &[(usize, String)], width: usize, gap: usize) -> String {
+    let blocks: Vec<Vec<String>> = blocks.iter().map(|(number, rendered)| {
+        let mut lines = vec![format!("#{number}")];
+        lines.extend(rendered.trim_end_matches('\n').lines().map(String::from));
+        lines
+    }).collect();
+    let widths: Vec<usize> = blocks.iter()
+        .map(|lines| lines.iter().map(|l| visible_width(l)).max().unwrap_or(0))
+        .collect();
+
+    let mut rows: Vec<Vec<usize>> = Vec::new();
+    let mut used = 0;
+    for (idx, &block_width) in widths.iter().enumerate() {
+        match rows.last_mut() {
+            Some(row) if used + gap + block_width <= width => {
+                row.push(idx);
+                used += gap + block_width;
+            },
+            _ => {
+                rows.push(vec![idx]);
+                used = block_width;
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for row in rows {
+        let height = row.iter().map(|&idx| blocks[idx].len()).max().unwrap_or(0);
+        for line_id in 0..height {
+            let mut line = String::new();
+            for (pos, &idx) in row.iter().enumerate() {
+                let content = blocks[idx].get(line_id).map_or("", |l| l.as_str());
+                line.push_str(content);
+                if pos + 1 < row.len() {
+                    let padding = widths[idx] - visible_width(content) + gap;
+                    line.push_str(&" ".repeat(padding));
+                }
+            }
+            out.push(line.trim_end().to_string());
+        }
+        out.push(String::new());
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_ignores_escapes() {
+        assert_eq!(visible_width("\x1b[41m  \x1b[48;5;214m░░\x1b[0m"), 4);
+        assert_eq!(visible_width("│ I │"), 5);
+    }
+
+    #[test]
+    fn packs_rows() {
+        let blocks = vec![(1, "ab\ncd\n".to_string()),
+                          (2, "ef\ngh\n".to_string()),
+                          (3, "ij\n".to_string())];
+        assert_eq!(pack(&blocks, 6, 2), "#1  #2\nab  ef\ncd  gh\n\n#3\nij\n");
+        assert_eq!(pack(&blocks, 80, 1), "#1 #2 #3\nab ef ij\ncd gh\n");
+        assert_eq!(pack(&blocks, 1, 2), "#1\nab\ncd\n\n#2\nef\ngh\n\n#3\nij\n");
+    }
+}
@@ -0,0 +1,86 @@
+use crate::bitmap::Bitmap2D;
+use crate::piece::PaddedPieces;
+use crate::solver::{increment, TimedOut};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// Tilings per piece multiset, indexed like `PIECE_ORDER`.
+pub type Counts = HashMap<[u32; 7], u64>;
+
+/// Completions per position and board.
+type Memo = HashMap<((usize, usize), Vec<u64>), Rc<Counts>>;
+
+/// Number of boards past which completions are no longer memoized.
+const MEMO_LIMIT: usize = 1 << 20;
+
+/// Counts the tilings of `board` with any tetrominoes, per piece multiset.
+/// The board is filled cell by cell like `fill_board` does, so the filled
+/// cells past the current one are all that is left to tile: completions
+/// are memoized on them and shared by every multiset. Gives up at
+/// `deadline` if any.
+pub fn tilings_per_multiset(board: &Bitmap2D, padded_pieces: &PaddedPieces,
+                            deadline: Option<Instant>) -> Result<Counts, TimedOut> {
+    let mut memo = HashMap::new();
+    let counts = completions(board, Some((0, 0)), padded_pieces, &mut memo, deadline)?;
+    Ok(Rc::try_unwrap(counts).unwrap_or_else(|counts| (*counts).clone()))
+}
+
+fn completions(board: &Bitmap2D, position: Option<(usize, usize)>, padded_pieces: &PaddedPieces,
+               memo: &mut Memo, deadline: Option<Instant>) -> Result<Rc<Counts>, TimedOut> {
+    let Some(position) = position else {
+        return Ok(Rc::new(HashMap::from([([0; 7], 1)])));
+    };
+    let next_pos = increment(board.shape, position);
+    if board.get(position).unwrap_or(false) {
+        return completions(board, next_pos, padded_pieces, memo, deadline);
+    }
+    let key = (position, board.data.clone());
+    if let Some(counts) = memo.get(&key) {
+        return Ok(counts.clone());
+    }
+    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return Err(TimedOut);
+    }
+    let mut counts = Counts::new();
+    for (piece_id, piece_dict) in padded_pieces.iter().enumerate() {
+        let Some(variants) = piece_dict.get(&position) else { continue };
+        for variant in variants {
+            if board.intersects(&variant.bitmap) { continue }
+            let rest = completions(&board.or(&variant.bitmap), next_pos, padded_pieces, memo,
+                                   deadline)?;
+            for (pieces, n) in rest.iter() {
+                let mut pieces = *pieces;
+                pieces[piece_id] += 1;
+                *counts.entry(pieces).or_insert(0) += n;
+            }
+        }
+    }
+    let counts = Rc::new(counts);
+    if memo.len() < MEMO_LIMIT {
+        memo.insert(key, counts.clone());
+    }
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::{get_padded_pieces, parse_pieces};
+    use crate::solver::all_solutions;
+
+    #[test]
+    fn matches_all_solutions() {
+        let board = Bitmap2D::zeros((4, 5));
+        let tables = get_padded_pieces(board.shape);
+        let counts = tilings_per_multiset(&board, &tables, None).unwrap();
+        assert!(counts.keys().all(|pieces| pieces.iter().sum::<u32>() == 5));
+        for (pieces, &n) in &counts {
+            assert_eq!(all_solutions(&board, *pieces, (0, 0), &tables).len() as u64, n);
+        }
+        // Five upright I pieces, or four flat ones beside an upright one.
+        assert_eq!(counts.get(&parse_pieces("IIIII").unwrap()), Some(&3));
+        assert!(!counts.contains_key(&parse_pieces("TTTTT").unwrap()));
+        assert!(tilings_per_multiset(&board, &tables, Some(Instant::now())).is_err());
+    }
+}
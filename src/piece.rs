@@ -1,71 +1,115 @@
 use crate::bitmap::Bitmap2D;
+use crate::error::TalosError;
 use std::collections::HashMap;
 
 pub struct PieceVariant {
     pub bitmap: Bitmap2D,
     //pub origin: (u32, u32),
     pub origin: (usize, usize),
+    /// Clockwise quarter turns from the spawn orientation, the one a piece
+    /// has when picked up in the game: I, S and Z flat, and J, L and T with
+    /// their flat side down.
+    pub rotation: usize,
 }
 
 pub struct Piece {
     pub variants: Vec<PieceVariant>
 }
 
+/// A piece variant padded to the board size, as stored in the placement
+/// tables built by `get_padded_pieces`.
+pub struct Placement {
+    pub piece: char,
+    pub variant: usize,
+    pub rotation: usize,
+    pub origin: (usize, usize),
+    pub bitmap: Bitmap2D,
+}
+
+pub type PaddedPieces = Vec<HashMap<(usize, usize), Vec<Placement>>>;
+
 pub const PIECE_ORDER: [char ; 7] = ['J', 'I', 'L', 'T', 'S', 'Z', 'O'];
 
 pub fn get_standard_pieces() -> HashMap<char, Piece> {
     let mut pieces_by_name = HashMap::new();
 
     pieces_by_name.insert('J', Piece { variants:
-            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b010111 << 58] }, origin: (0,1) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b111010 << 58] }, origin: (0,0) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b100111 << 58] }, origin: (0,0) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b111001 << 58] }, origin: (0,0) }] });
+            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b010111 << 58] }, origin: (0,1), rotation: 3 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b111010 << 58] }, origin: (0,0), rotation: 1 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b100111 << 58] }, origin: (0,0), rotation: 0 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b111001 << 58] }, origin: (0,0), rotation: 2 }] });
 
     pieces_by_name.insert('I', Piece { variants:
-            vec![PieceVariant { bitmap: Bitmap2D { shape: (1, 4), data: vec![0b1111 << 60] }, origin: (0,0) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (4, 1), data: vec![0b1111 << 60] }, origin: (0,0) }] });
+            vec![PieceVariant { bitmap: Bitmap2D { shape: (1, 4), data: vec![0b1111 << 60] }, origin: (0,0), rotation: 0 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (4, 1), data: vec![0b1111 << 60] }, origin: (0,0), rotation: 1 }] });
 
     pieces_by_name.insert('L', Piece { variants:
-            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b101011 << 58] }, origin: (0,0) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b110101 << 58] }, origin: (0,0) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b001111 << 58] }, origin: (0,2) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b111100 << 58] }, origin: (0,0) }] });
+            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b101011 << 58] }, origin: (0,0), rotation: 1 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b110101 << 58] }, origin: (0,0), rotation: 3 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b001111 << 58] }, origin: (0,2), rotation: 0 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b111100 << 58] }, origin: (0,0), rotation: 2 }] });
 
     pieces_by_name.insert('T', Piece { variants:
-            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b011101 << 58] }, origin: (0,1) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b101110 << 58] }, origin: (0,0) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b010111 << 58] }, origin: (0,1) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b111010 << 58] }, origin: (0,0) }] });
+            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b011101 << 58] }, origin: (0,1), rotation: 3 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b101110 << 58] }, origin: (0,0), rotation: 1 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b010111 << 58] }, origin: (0,1), rotation: 0 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b111010 << 58] }, origin: (0,0), rotation: 2 }] });
 
     pieces_by_name.insert('S', Piece { variants:
-            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b101101 << 58] }, origin: (0,0) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b011110 << 58] }, origin: (0,1) }] });
+            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b101101 << 58] }, origin: (0,0), rotation: 1 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b011110 << 58] }, origin: (0,1), rotation: 0 }] });
 
     pieces_by_name.insert('Z', Piece { variants:
-            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b011110 << 58] }, origin: (0,1) },
-                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b110011 << 58] }, origin: (0,0) }] });
+            vec![PieceVariant { bitmap: Bitmap2D { shape: (3, 2), data: vec![0b011110 << 58] }, origin: (0,1), rotation: 1 },
+                 PieceVariant { bitmap: Bitmap2D { shape: (2, 3), data: vec![0b110011 << 58] }, origin: (0,0), rotation: 0 }] });
 
     pieces_by_name.insert('O', Piece { variants:
-            vec![PieceVariant { bitmap: Bitmap2D { shape: (2, 2), data: vec![0b1111 << 60] }, origin: (0,0) }]});
+            vec![PieceVariant { bitmap: Bitmap2D { shape: (2, 2), data: vec![0b1111 << 60] }, origin: (0,0), rotation: 0 }]});
 
     pieces_by_name
 }
 
-pub fn get_padded_pieces(board_size: (usize, usize)) -> Vec<HashMap<(usize, usize), Vec<Bitmap2D>>> {
+pub fn parse_pieces(pieces_str: &str) -> Result<[u32; 7], TalosError> {
+    let mut piece_count = [0; 7];
+    for (offset, piece_name) in pieces_str.chars().enumerate() {
+        let piece_id = PIECE_ORDER.iter()
+            .position(|&c| c == piece_name.to_ascii_uppercase())
+            .ok_or_else(|| TalosError::parse_at(
+                pieces_str, offset, format!("Unrecognized piece name '{piece_name}'")))?;
+        piece_count[piece_id] += 1;
+    }
+    Ok(piece_count)
+}
+
+pub fn pieces_to_string(piece_count: &[u32; 7]) -> String {
+    PIECE_ORDER.iter().zip(piece_count)
+        .map(|(letter, &count)| letter.to_string().repeat(count as usize))
+        .collect()
+}
+
+pub fn get_padded_pieces(board_size: (usize, usize)) -> PaddedPieces {
     let pieces_by_name = get_standard_pieces();
     let mut out = Vec::with_capacity(7);
     for piece_letter in PIECE_ORDER {
-        let mut position_dic: HashMap<(usize, usize), Vec<Bitmap2D>> =
+        let mut position_dic: HashMap<(usize, usize), Vec<Placement>> =
             HashMap::new();
         let piece = pieces_by_name.get(&piece_letter).unwrap();
-        for variant in piece.variants.iter() {
+        for (variant_id, variant) in piece.variants.iter().enumerate() {
+            if variant.bitmap.shape.0 > board_size.0 || variant.bitmap.shape.1 > board_size.1 {
+                continue;
+            }
             for offset_x in 0..(board_size.0 - variant.bitmap.shape.0 + 1) {
                 for offset_y in 0..(board_size.1 - variant.bitmap.shape.1 + 1) {
-                    let padded = variant.bitmap.pad_to(board_size,
-                                                       (offset_x, offset_y));
-                    let new_origin = (offset_x + variant.origin.0,
-                                      offset_y + variant.origin.1);
+                    let padded = Placement {
+                        piece: piece_letter,
+                        variant: variant_id,
+                        rotation: variant.rotation,
+                        origin: (offset_x + variant.origin.0,
+                                 offset_y + variant.origin.1),
+                        bitmap: variant.bitmap.pad_to(board_size,
+                                                      (offset_x, offset_y)),
+                    };
+                    let new_origin = padded.origin;
                     match position_dic.get_mut(&new_origin) {
                         Some(vec) => vec.push(padded),
                         None => { position_dic.insert(new_origin, vec![padded]); },
@@ -95,3 +139,39 @@ pub fn get_padded_pieces(board_size: (usize, usize)) -> Vec<HashMap<(usize, usiz
 //              fill_board(new_board, rclone, position+1)
 // ajouter une condition de succès ou d'échec
 // ajouter un moyen de récupérer la solution
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_piece_names() {
+        assert_eq!(parse_pieces("IIIIJJLLSZ").unwrap(), [2, 4, 2, 0, 1, 1, 0]);
+        assert_eq!(parse_pieces("oO").unwrap(), [0, 0, 0, 0, 0, 0, 2]);
+        assert!(matches!(parse_pieces("IX"), Err(TalosError::Parse { column: 2, .. })));
+        assert_eq!(pieces_to_string(&[2, 4, 2, 0, 1, 1, 0]), "JJIIIILLSZ");
+    }
+
+    fn rotate_cw(bitmap: &Bitmap2D) -> Bitmap2D {
+        let mut rotated = Bitmap2D::zeros((bitmap.shape.1, bitmap.shape.0));
+        for (row, col) in bitmap.ones() {
+            rotated.set((col, bitmap.shape.0 - 1 - row));
+        }
+        rotated
+    }
+
+    #[test]
+    fn rotations_from_spawn() {
+        for (letter, piece) in get_standard_pieces() {
+            let spawn = piece.variants.iter().find(|v| v.rotation == 0).unwrap();
+            for variant in &piece.variants {
+                let mut bitmap = Bitmap2D { shape: spawn.bitmap.shape, data: spawn.bitmap.data.clone() };
+                for _ in 0..variant.rotation {
+                    bitmap = rotate_cw(&bitmap);
+                }
+                assert_eq!(bitmap.to_string(), variant.bitmap.to_string(),
+                           "{letter} rotated {} times", variant.rotation);
+            }
+        }
+    }
+}
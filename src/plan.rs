@@ -0,0 +1,159 @@
+use crate::bitmap::Bitmap2D;
+use crate::piece::{get_padded_pieces, PaddedPieces};
+use crate::solver::Solver;
+use std::collections::HashMap;
+
+/// A door to open: a board and, unless any mix of pieces will do, the
+/// pieces it requires.
+pub struct Door {
+    pub name: String,
+    pub size: (usize, usize),
+    pub pieces: Option<[u32; 7]>,
+}
+
+impl Door {
+    fn n_pieces(&self) -> u32 {
+        (self.size.0*self.size.1 / 4) as u32
+    }
+}
+
+/// Piece multisets drawn from `available` with `total` pieces in all.
+pub fn sub_multisets(available: &[u32; 7], total: u32) -> Vec<[u32; 7]> {
+    fn fill(available: &[u32; 7], piece_id: usize, left: u32,
+            current: &mut [u32; 7], out: &mut Vec<[u32; 7]>) {
+        if piece_id == 7 {
+            if left == 0 { out.push(*current) }
+            return;
+        }
+        for count in (0..=available[piece_id].min(left)).rev() {
+            current[piece_id] = count;
+            fill(available, piece_id + 1, left - count, current, out);
+        }
+        current[piece_id] = 0;
+    }
+    let mut out = Vec::new();
+    fill(available, 0, total, &mut [0; 7], &mut out);
+    out
+}
+
+fn contains(inventory: &[u32; 7], pieces: &[u32; 7]) -> bool {
+    inventory.iter().zip(pieces).all(|(have, need)| have >= need)
+}
+
+/// Pieces left in `inventory` once `pieces` are used.
+pub fn minus(inventory: &[u32; 7], pieces: &[u32; 7]) -> [u32; 7] {
+    let mut left = *inventory;
+    for (have, used) in left.iter_mut().zip(pieces) {
+        *have -= used;
+    }
+    left
+}
+
+/// Decides which doors to open with a shared inventory, remembering which
+/// boards each multiset can tile.
+#[derive(Default)]
+pub struct Planner {
+    tables: HashMap<(usize, usize), PaddedPieces>,
+    tileable: HashMap<((usize, usize), [u32; 7]), bool>,
+    best: Vec<Option<[u32; 7]>>,
+    best_opened: usize,
+}
+
+impl Planner {
+    pub fn tiles(&mut self, size: (usize, usize), pieces: [u32; 7]) -> bool {
+        if 4*pieces.iter().sum::<u32>() as usize != size.0*size.1 {
+            return false;
+        }
+        if let Some(&known) = self.tileable.get(&(size, pieces)) {
+            return known;
+        }
+        let tables = self.tables.entry(size).or_insert_with(|| get_padded_pieces(size));
+        let found = Solver::new(tables).first(&Bitmap2D::zeros(size), pieces).is_some();
+        self.tileable.insert((size, pieces), found);
+        found
+    }
+
+    /// Pieces `door` would use if it were opened alone with `inventory`.
+    pub fn open_now(&mut self, door: &Door, inventory: &[u32; 7]) -> Option<[u32; 7]> {
+        match door.pieces {
+            Some(pieces) => (contains(inventory, &pieces) && self.tiles(door.size, pieces))
+                .then_some(pieces),
+            None => sub_multisets(inventory, door.n_pieces()).into_iter()
+                .find(|&pieces| self.tiles(door.size, pieces)),
+        }
+    }
+
+    /// Opens as many doors as possible at once, preferring earlier doors on
+    /// ties. Returns the pieces assigned to each door, `None` for doors left
+    /// closed.
+    pub fn plan(&mut self, doors: &[Door], inventory: [u32; 7]) -> Vec<Option<[u32; 7]>> {
+        self.best = vec![None; doors.len()];
+        self.best_opened = 0;
+        self.search(doors, inventory, &mut Vec::with_capacity(doors.len()), 0);
+        std::mem::take(&mut self.best)
+    }
+
+    fn search(&mut self, doors: &[Door], inventory: [u32; 7],
+              assigned: &mut Vec<Option<[u32; 7]>>, opened: usize) {
+        if assigned.len() == doors.len() {
+            if opened > self.best_opened {
+                self.best_opened = opened;
+                self.best = assigned.clone();
+            }
+            return;
+        }
+        if opened + doors.len() - assigned.len() <= self.best_opened {
+            return;
+        }
+        let door = &doors[assigned.len()];
+        let candidates = match door.pieces {
+            Some(pieces) if contains(&inventory, &pieces) => vec![pieces],
+            Some(_) => Vec::new(),
+            None => sub_multisets(&inventory, door.n_pieces()),
+        };
+        for pieces in candidates {
+            if !self.tiles(door.size, pieces) { continue }
+            assigned.push(Some(pieces));
+            self.search(doors, minus(&inventory, &pieces), assigned, opened + 1);
+            assigned.pop();
+            if self.best_opened == doors.len() { return }
+        }
+        assigned.push(None);
+        self.search(doors, inventory, assigned, opened);
+        assigned.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn door(size: (usize, usize), pieces: Option<[u32; 7]>) -> Door {
+        Door { name: String::new(), size, pieces }
+    }
+
+    #[test]
+    fn multisets() {
+        assert_eq!(sub_multisets(&[1, 0, 0, 0, 0, 0, 2], 2),
+                   vec![[1, 0, 0, 0, 0, 0, 1], [0, 0, 0, 0, 0, 0, 2]]);
+        assert!(sub_multisets(&[1, 0, 0, 0, 0, 0, 0], 2).is_empty());
+    }
+
+    #[test]
+    fn shares_inventory() {
+        let mut planner = Planner::default();
+        // Alone, the flexible door would take two I pieces, which the second
+        // door needs.
+        let inventory = [0, 4, 0, 0, 0, 0, 2];
+        let doors = [door((2, 4), None), door((4, 4), Some([0, 4, 0, 0, 0, 0, 0]))];
+        assert_eq!(planner.open_now(&doors[0], &inventory), Some([0, 2, 0, 0, 0, 0, 0]));
+        assert!(planner.open_now(&doors[1], &inventory).is_some());
+        assert_eq!(planner.plan(&doors, inventory),
+                   vec![Some([0, 0, 0, 0, 0, 0, 2]), Some([0, 4, 0, 0, 0, 0, 0])]);
+
+        let inventory = [0, 2, 0, 0, 0, 0, 2];
+        let doors = [door((4, 4), Some([0, 2, 0, 0, 0, 0, 2])), door((2, 4), None)];
+        assert_eq!(planner.plan(&doors, inventory), vec![Some([0, 2, 0, 0, 0, 0, 2]), None]);
+        assert_eq!(planner.plan(&doors, [0; 7]), vec![None, None]);
+    }
+}
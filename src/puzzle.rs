@@ -0,0 +1,362 @@
+use crate::bitmap::Bitmap2D;
+use crate::error::TalosError;
+use crate::piece::*;
+use std::fmt;
+
+/// A piece variant fixed on the board, its origin being the first cell of
+/// the variant in row-major order, as in `Placement`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fixed {
+    pub piece: char,
+    pub variant: usize,
+    pub origin: (usize, usize),
+}
+
+/// Restricts which piece types may cover a cell.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Constraint {
+    Cover((usize, usize), char),
+    Avoid((usize, usize), char),
+}
+
+/// A puzzle as described by a puzzle file:
+///
+/// ```text
+/// # Lines starting with '#' are comments, except inside the board.
+/// size 5 8
+/// pieces IIIIJJLLSZ
+/// board
+/// ........
+/// ...##...
+/// ........
+/// ........
+/// ........
+/// place I 0 0 0
+/// cover 4 7 Z
+/// avoid 0 7 O
+/// ```
+///
+/// `size W H` uses the same order as the command line. `pieces` lists every
+/// piece, pre-placed ones included. The optional `board` section has W rows
+/// of H cells, '.' free and '#' blocked. `place PIECE VARIANT ROW COL` fixes
+/// a variant with its origin at ROW COL, `cover ROW COL PIECE` requires the
+/// cell to be covered by that piece type and `avoid ROW COL PIECE` forbids it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Puzzle {
+    pub size: (usize, usize),
+    pub pieces: [u32; 7],
+    pub blocked: Vec<(usize, usize)>,
+    pub placed: Vec<Fixed>,
+    pub constraints: Vec<Constraint>,
+}
+
+/// Whitespace-separated words of `line` with their char offset.
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (chars, (byte, c)) in line.char_indices().enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some((chars, byte)),
+            (true, Some((offset, from))) => {
+                out.push((offset, &line[from..byte]));
+                start = None;
+            },
+            _ => {},
+        }
+    }
+    if let Some((offset, from)) = start {
+        out.push((offset, &line[from..]));
+    }
+    out
+}
+
+fn piece_id(letter: char) -> Option<usize> {
+    PIECE_ORDER.iter().position(|&c| c == letter.to_ascii_uppercase())
+}
+
+impl Puzzle {
+    pub fn new(size: (usize, usize), pieces: [u32; 7]) -> Self {
+        Self { size, pieces, blocked: Vec::new(), placed: Vec::new(), constraints: Vec::new() }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, TalosError> {
+        let error = |line_id: usize, offset: usize, message: String| TalosError::Parse {
+            input: text.to_string(), line: line_id + 1, column: offset + 1, message
+        };
+        let standard = get_standard_pieces();
+        let mut size = None;
+        let mut pieces = None;
+        let mut puzzle = Puzzle::new((0, 0), [0; 7]);
+        let mut has_board = false;
+        let n_lines = text.lines().count();
+        let mut lines = text.lines().enumerate();
+
+        while let Some((line_id, line)) = lines.next() {
+            let words = words(line);
+            let Some(&(keyword_offset, keyword)) = words.first() else { continue };
+            if keyword.starts_with('#') { continue }
+            let args = &words[1..];
+            let expect = |n: usize, usage: &str| {
+                if args.len() == n { return Ok(()) }
+                let offset = args.get(n).map_or(line.chars().count(), |&(offset, _)| offset);
+                Err(error(line_id, offset, format!("Expected '{keyword} {usage}'")))
+            };
+            let number = |(offset, word): (usize, &str)| word.parse::<usize>()
+                .map_err(|_| error(line_id, offset, format!("Expected a number, found '{word}'")));
+            let letter = |(offset, word): (usize, &str)| {
+                let mut chars = word.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if piece_id(c).is_some() => Ok(c.to_ascii_uppercase()),
+                    _ => Err(error(line_id, offset, format!("Unrecognized piece name '{word}'"))),
+                }
+            };
+            let cell = |row: (usize, &str), col: (usize, &str)| {
+                let cell = (number(row)?, number(col)?);
+                if cell.0 >= puzzle.size.0 || cell.1 >= puzzle.size.1 {
+                    return Err(error(line_id, row.0, format!(
+                        "Cell {} {} is outside the {}x{} board",
+                        cell.0, cell.1, puzzle.size.0, puzzle.size.1)));
+                }
+                Ok(cell)
+            };
+            if keyword != "size" && size.is_none() {
+                return Err(error(line_id, keyword_offset,
+                                 format!("Expected 'size W H' before '{keyword}'")));
+            }
+
+            match keyword {
+                "size" => {
+                    expect(2, "W H")?;
+                    if size.is_some() {
+                        return Err(error(line_id, keyword_offset, "Duplicate 'size'".to_string()));
+                    }
+                    puzzle.size = (number(args[0])?, number(args[1])?);
+                    size = Some(puzzle.size);
+                },
+                "pieces" => {
+                    expect(1, "PIECES")?;
+                    let (offset, word) = args[0];
+                    pieces = Some(parse_pieces(word).map_err(|err| match err {
+                        TalosError::Parse { column, message, .. } =>
+                            error(line_id, offset + column - 1, message),
+                        err => err,
+                    })?);
+                },
+                "board" => {
+                    expect(0, "")?;
+                    if has_board {
+                        return Err(error(line_id, keyword_offset, "Duplicate 'board'".to_string()));
+                    }
+                    has_board = true;
+                    let (rows, cols) = puzzle.size;
+                    for row in 0..rows {
+                        let Some((line_id, line)) = lines.next() else {
+                            return Err(error(n_lines - 1, 0, format!(
+                                "Board ends early, expected {rows} rows")));
+                        };
+                        let cells: Vec<char> = line.trim_end().chars().collect();
+                        if let Some(col) = cells.iter().position(|&c| c != '.' && c != '#') {
+                            return Err(error(line_id, col, format!(
+                                "Unexpected board cell '{}', expected '.' or '#'", cells[col])));
+                        }
+                        if cells.len() != cols {
+                            return Err(error(line_id, cells.len().min(cols), format!(
+                                "Board row has {} cells, expected {cols}", cells.len())));
+                        }
+                        puzzle.blocked.extend((0..cols).filter(|&col| cells[col] == '#')
+                                              .map(|col| (row, col)));
+                    }
+                },
+                "place" => {
+                    expect(4, "PIECE VARIANT ROW COL")?;
+                    let piece = letter(args[0])?;
+                    let variant = number(args[1])?;
+                    let n_variants = standard[&piece].variants.len();
+                    if variant >= n_variants {
+                        return Err(error(line_id, args[1].0, format!(
+                            "Piece {piece} has variants 0 to {}", n_variants - 1)));
+                    }
+                    let origin = cell(args[2], args[3])?;
+                    puzzle.placed.push(Fixed { piece, variant, origin });
+                },
+                "cover" | "avoid" => {
+                    expect(3, "ROW COL PIECE")?;
+                    let cell = cell(args[0], args[1])?;
+                    let piece = letter(args[2])?;
+                    puzzle.constraints.push(if keyword == "cover" {
+                        Constraint::Cover(cell, piece)
+                    } else {
+                        Constraint::Avoid(cell, piece)
+                    });
+                },
+                _ => return Err(error(line_id, keyword_offset,
+                                      format!("Unrecognized keyword '{keyword}'"))),
+            }
+        }
+
+        puzzle.pieces = pieces.ok_or_else(|| error(n_lines.max(1) - 1, 0,
+                                                   "Missing 'pieces PIECES'".to_string()))?;
+        Ok(puzzle)
+    }
+
+    /// Placement tables for the board, without the placements the
+    /// constraints rule out.
+    pub fn placement_tables(&self) -> PaddedPieces {
+        let mut tables = get_padded_pieces(self.size);
+        for constraint in &self.constraints {
+            let (cell, piece, keep_piece) = match *constraint {
+                Constraint::Cover(cell, piece) => (cell, piece, true),
+                Constraint::Avoid(cell, piece) => (cell, piece, false),
+            };
+            for table in tables.iter_mut() {
+                for placements in table.values_mut() {
+                    placements.retain(|p| !p.bitmap.get(cell).unwrap_or(false)
+                                          || (p.piece == piece) == keep_piece);
+                }
+            }
+        }
+        tables
+    }
+
+    /// Starting board with blocked cells and pre-placed pieces filled in,
+    /// the pieces left to place and the pre-placed pieces, looked up in
+    /// `tables`.
+    pub fn start<'a>(&self, tables: &'a PaddedPieces)
+                     -> Result<(Bitmap2D, [u32; 7], Vec<&'a Placement>), TalosError> {
+        let mut board = Bitmap2D::zeros(self.size);
+        for &cell in &self.blocked {
+            board.set(cell);
+        }
+        let mut remaining = self.pieces;
+        let mut placed = Vec::with_capacity(self.placed.len());
+        for fixed in &self.placed {
+            let piece_id = piece_id(fixed.piece).unwrap();
+            let description = format!("{} {} at {} {}", fixed.piece, fixed.variant,
+                                      fixed.origin.0, fixed.origin.1);
+            let placement = tables[piece_id].get(&fixed.origin)
+                .and_then(|placements| placements.iter().find(|p| p.variant == fixed.variant))
+                .ok_or_else(|| TalosError::InvalidBoard(format!(
+                    "{description} does not fit the board or breaks a constraint")))?;
+            if board.intersects(&placement.bitmap) {
+                return Err(TalosError::InvalidBoard(format!(
+                    "{description} overlaps a blocked cell or another piece")));
+            }
+            if remaining[piece_id] == 0 {
+                return Err(TalosError::InvalidBoard(format!(
+                    "{description} is not in the piece list")));
+            }
+            remaining[piece_id] -= 1;
+            board = board.or(&placement.bitmap);
+            placed.push(placement);
+        }
+        Ok((board, remaining, placed))
+    }
+
+    /// The same puzzle with every piece of `solution` fixed on the board.
+    pub fn solved(&self, solution: &[&Placement]) -> Self {
+        let placed = solution.iter()
+            .map(|p| Fixed { piece: p.piece, variant: p.variant, origin: p.origin })
+            .collect();
+        Self { placed, ..self.clone() }
+    }
+}
+
+impl fmt::Display for Puzzle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "size {} {}", self.size.0, self.size.1)?;
+        writeln!(f, "pieces {}", pieces_to_string(&self.pieces))?;
+        if !self.blocked.is_empty() {
+            writeln!(f, "board")?;
+            for row in 0..self.size.0 {
+                let line: String = (0..self.size.1)
+                    .map(|col| if self.blocked.contains(&(row, col)) { '#' } else { '.' })
+                    .collect();
+                writeln!(f, "{line}")?;
+            }
+        }
+        for fixed in &self.placed {
+            writeln!(f, "place {} {} {} {}", fixed.piece, fixed.variant,
+                     fixed.origin.0, fixed.origin.1)?;
+        }
+        for constraint in &self.constraints {
+            match constraint {
+                Constraint::Cover((row, col), piece) => writeln!(f, "cover {row} {col} {piece}")?,
+                Constraint::Avoid((row, col), piece) => writeln!(f, "avoid {row} {col} {piece}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::Solver;
+
+    const FILE: &str = "\
+# 3x4 with a blocked corner
+size 3 4
+pieces IO
+board
+##..
+##..
+....
+cover 2 0 I
+";
+
+    #[test]
+    fn parse_and_solve() {
+        let puzzle = Puzzle::parse(FILE).unwrap();
+        assert_eq!(puzzle.size, (3, 4));
+        assert_eq!(puzzle.pieces, [0, 1, 0, 0, 0, 0, 1]);
+        assert_eq!(puzzle.blocked, vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+        assert_eq!(puzzle.constraints, vec![Constraint::Cover((2, 0), 'I')]);
+
+        let tables = puzzle.placement_tables();
+        let (board, remaining, placed) = puzzle.start(&tables).unwrap();
+        assert_eq!(board.ones().len(), 4);
+        assert_eq!(remaining, puzzle.pieces);
+        assert!(placed.is_empty());
+        let solution = Solver::new(&tables).first(&board, remaining).unwrap();
+        assert_eq!(solution.iter().map(|p| p.piece).collect::<String>(), "OI");
+    }
+
+    #[test]
+    fn round_trip() {
+        let puzzle = Puzzle::parse(FILE).unwrap();
+        assert_eq!(Puzzle::parse(&puzzle.to_string()).unwrap(), puzzle);
+
+        let tables = puzzle.placement_tables();
+        let (board, remaining, _) = puzzle.start(&tables).unwrap();
+        let solution = Solver::new(&tables).first(&board, remaining).unwrap();
+        let solved = puzzle.solved(&solution);
+        let text = solved.to_string();
+        assert!(text.ends_with("place O 0 0 2\nplace I 0 2 0\ncover 2 0 I\n"));
+
+        let reparsed = Puzzle::parse(&text).unwrap();
+        let (board, remaining, placed) = reparsed.start(&tables).unwrap();
+        assert_eq!(board.ones().len(), 12);
+        assert_eq!(remaining, [0; 7]);
+        assert_eq!(placed.len(), 2);
+    }
+
+    #[test]
+    fn errors() {
+        let err = Puzzle::parse("size 2 4\npieces IX\n").unwrap_err();
+        assert!(matches!(err, TalosError::Parse { line: 2, column: 9, .. }), "{err}");
+        let err = Puzzle::parse("pieces II\n").unwrap_err();
+        assert!(matches!(err, TalosError::Parse { line: 1, column: 1, .. }), "{err}");
+        let err = Puzzle::parse("size 2 4\npieces II\nboard\n....\n..x.\n").unwrap_err();
+        assert!(matches!(err, TalosError::Parse { line: 5, column: 3, .. }), "{err}");
+        let err = Puzzle::parse("size 2 4\npieces II\nplace I 2 0 0\n").unwrap_err();
+        assert!(matches!(err, TalosError::Parse { line: 3, column: 9, .. }), "{err}");
+        let err = Puzzle::parse("size 2 4\npieces II\navoid 2 0 I\n").unwrap_err();
+        assert!(matches!(err, TalosError::Parse { line: 3, column: 7, .. }), "{err}");
+
+        let puzzle = Puzzle::parse("size 2 4\npieces II\nplace I 1 0 0\n").unwrap();
+        let tables = puzzle.placement_tables();
+        assert!(matches!(puzzle.start(&tables), Err(TalosError::InvalidBoard(_))));
+        let puzzle = Puzzle::parse("size 2 4\npieces I\nplace I 0 0 0\nplace I 0 1 0\n").unwrap();
+        assert!(matches!(puzzle.start(&tables), Err(TalosError::InvalidBoard(_))));
+    }
+}
@@ -0,0 +1,338 @@
+use crate::bitmap::Bitmap2D;
+use crate::piece::{Placement, PIECE_ORDER};
+
+/// Number of distinct colors `Bitmap2D::print_all` can express besides the
+/// empty cell ('1' to 'f').
+pub const PALETTE_SIZE: usize = 15;
+
+/// Index of the placement covering each cell of the board, if any.
+pub fn owner_grid(shape: (usize, usize), placements: &[&Placement]) -> Vec<Vec<Option<usize>>> {
+    let mut grid = vec![vec![None; shape.1]; shape.0];
+    for (idx, placement) in placements.iter().enumerate() {
+        for (row, col) in placement.bitmap.ones() {
+            grid[row][col] = Some(idx);
+        }
+    }
+    grid
+}
+
+/// For each placement, the sorted indices of the placements sharing an edge
+/// with it.
+pub fn adjacency(shape: (usize, usize), placements: &[&Placement]) -> Vec<Vec<usize>> {
+    let grid = owner_grid(shape, placements);
+    let mut neighbours = vec![Vec::new(); placements.len()];
+    let mut link = |a: Option<usize>, b: Option<usize>| {
+        if let (Some(a), Some(b)) = (a, b) && a != b {
+            neighbours[a].push(b);
+            neighbours[b].push(a);
+        }
+    };
+    for row in 0..shape.0 {
+        for col in 0..shape.1 {
+            if row + 1 < shape.0 { link(grid[row][col], grid[row + 1][col]); }
+            if col + 1 < shape.1 { link(grid[row][col], grid[row][col + 1]); }
+        }
+    }
+    for list in neighbours.iter_mut() {
+        list.sort_unstable();
+        list.dedup();
+    }
+    neighbours
+}
+
+/// Colors more saturated pieces first, then pieces with more neighbours,
+/// then earlier pieces, as DSATUR does.
+fn next_to_color(neighbours: &[Vec<usize>], colors: &[Option<usize>]) -> Option<usize> {
+    let saturation = |idx: usize| {
+        let mut used: Vec<usize> = neighbours[idx].iter().filter_map(|&n| colors[n]).collect();
+        used.sort_unstable();
+        used.dedup();
+        used.len()
+    };
+    (0..colors.len())
+        .filter(|&idx| colors[idx].is_none())
+        .max_by_key(|&idx| (saturation(idx), neighbours[idx].len(), std::cmp::Reverse(idx)))
+}
+
+/// DSATUR taking the smallest color in `0..palette_size` that no neighbour
+/// has, and backtracking when a piece has none left, for at most `budget`
+/// tries.
+fn dsatur(neighbours: &[Vec<usize>], colors: &mut [Option<usize>], palette_size: usize,
+          budget: &mut usize) -> bool {
+    let Some(next) = next_to_color(neighbours, colors) else { return true };
+    for color in 0..palette_size {
+        if neighbours[next].iter().any(|&n| colors[n] == Some(color)) { continue }
+        if *budget == 0 { return false }
+        *budget -= 1;
+        colors[next] = Some(color);
+        if dsatur(neighbours, colors, palette_size, budget) { return true }
+        colors[next] = None;
+    }
+    false
+}
+
+/// Assigns a color in `0..palette_size` to each placement so that pieces
+/// sharing an edge never get the same color. Pieces on a board only ever
+/// need 4 colors, but when no such coloring is found, each piece gets the
+/// color fewest of its neighbours have and the second value is false.
+pub fn color_pieces(shape: (usize, usize), placements: &[&Placement],
+                    palette_size: usize) -> (Vec<usize>, bool) {
+    let neighbours = adjacency(shape, placements);
+    let palette_size = palette_size.max(1);
+    let mut colors: Vec<Option<usize>> = vec![None; placements.len()];
+    let mut budget = 100_000;
+    let distinct = dsatur(&neighbours, &mut colors, palette_size, &mut budget);
+    if !distinct {
+        colors = vec![None; placements.len()];
+        while let Some(next) = next_to_color(&neighbours, &colors) {
+            let clashes = |c: usize| neighbours[next].iter().filter(|&&n| colors[n] == Some(c)).count();
+            colors[next] = (0..palette_size).min_by_key(|&c| clashes(c));
+        }
+    }
+    (colors.into_iter().map(Option::unwrap).collect(), distinct)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorBy {
+    /// Distinct colors for neighbouring pieces, see `color_pieces`.
+    Neighbour,
+    /// One color per piece type, so all J's look the same.
+    Piece,
+}
+
+impl std::str::FromStr for ColorBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "neighbour" => Ok(ColorBy::Neighbour),
+            "piece" => Ok(ColorBy::Piece),
+            _ => Err(format!("Unrecognized coloring: {s} (expected neighbour or piece)"))
+        }
+    }
+}
+
+/// Colors each placement by its piece type, following `PIECE_ORDER`.
+pub fn piece_type_colors(placements: &[&Placement], palette_size: usize) -> Vec<usize> {
+    placements.iter()
+        .map(|p| PIECE_ORDER.iter().position(|&c| c == p.piece).unwrap() % palette_size.max(1))
+        .collect()
+}
+
+/// Same output as `Bitmap2D::print_all`, but with the hex digit of each
+/// piece chosen from `colors` (color `c` is drawn as digit `c + 1`).
+pub fn color_grid(shape: (usize, usize), placements: &[&Placement], colors: &[usize]) -> String {
+    let n_layers = colors.iter().map(|c| c % PALETTE_SIZE).max().map_or(1, |c| c + 1);
+    let mut layers: Vec<Bitmap2D> = (0..n_layers).map(|_| Bitmap2D::zeros(shape)).collect();
+    for (placement, &color) in placements.iter().zip(colors) {
+        let layer = color % PALETTE_SIZE;
+        layers[layer] = layers[layer].or(&placement.bitmap);
+    }
+    Bitmap2D::print_all(layers.iter())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Cell {
+    Outside,
+    Empty,
+    Piece(usize),
+}
+
+fn cell_at(grid: &[Vec<Option<usize>>], row: Option<usize>, col: Option<usize>) -> Cell {
+    let (Some(row), Some(col)) = (row, col) else { return Cell::Outside };
+    match grid.get(row).and_then(|line| line.get(col)) {
+        None => Cell::Outside,
+        Some(None) => Cell::Empty,
+        Some(Some(idx)) => Cell::Piece(*idx),
+    }
+}
+
+fn junction(up: bool, down: bool, left: bool, right: bool) -> char {
+    match (up, down, left, right) {
+        (false, false, false, false) => ' ',
+        (_, _, false, false) => '│',
+        (false, false, _, _) => '─',
+        (false, true, false, true) => '┌',
+        (false, true, true, false) => '┐',
+        (true, false, false, true) => '└',
+        (true, false, true, false) => '┘',
+        (true, true, false, true) => '├',
+        (true, true, true, false) => '┤',
+        (false, true, true, true) => '┬',
+        (true, false, true, true) => '┴',
+        (true, true, true, true) => '┼',
+    }
+}
+
+/// Renders a solution as a grid where borders are only drawn between cells
+/// belonging to different pieces, each cell labelled with its piece letter.
+pub fn to_box_drawing(shape: (usize, usize), placements: &[&Placement]) -> String {
+    let grid = owner_grid(shape, placements);
+    let at = |row: usize, col: usize, drow: isize, dcol: isize| {
+        cell_at(&grid, row.checked_add_signed(drow), col.checked_add_signed(dcol))
+    };
+    let mut lines = Vec::with_capacity(2*shape.0 + 1);
+    for row in 0..=shape.0 {
+        // Border line above `row`.
+        let mut border = String::new();
+        for col in 0..=shape.1 {
+            let (ul, ur) = (at(row, col, -1, -1), at(row, col, -1, 0));
+            let (dl, dr) = (at(row, col, 0, -1), at(row, col, 0, 0));
+            border.push(junction(ul != ur, dl != dr, ul != dl, ur != dr));
+            if col < shape.1 {
+                border.push_str(if ur != dr { "───" } else { "   " });
+            }
+        }
+        lines.push(border.trim_end().to_string());
+        if row == shape.0 { break }
+
+        let mut content = String::new();
+        for col in 0..=shape.1 {
+            content.push(if at(row, col, 0, -1) != at(row, col, 0, 0) { '│' } else { ' ' });
+            if col < shape.1 {
+                match at(row, col, 0, 0) {
+                    Cell::Piece(idx) => content.push_str(&format!(" {} ", placements[idx].piece)),
+                    _ => content.push_str("   "),
+                }
+            }
+        }
+        lines.push(content.trim_end().to_string());
+    }
+    lines.join("\n")
+}
+
+/// Top-left corner of the bounding box of a placed piece.
+pub fn top_left(placement: &Placement) -> (usize, usize) {
+    let cells = placement.bitmap.ones();
+    (cells.iter().map(|c| c.0).min().unwrap_or(0), cells.iter().map(|c| c.1).min().unwrap_or(0))
+}
+
+/// Steps to enter a solution in the game: each piece with its clockwise
+/// turns from the spawn orientation and the top-left corner of its bounding
+/// box, in reading order of those corners.
+pub fn to_instructions(placements: &[&Placement]) -> String {
+    let mut ordered = placements.to_vec();
+    ordered.sort_by_key(|p| top_left(p));
+    to_steps(&ordered)
+}
+
+/// Clockwise turns from the spawn orientation, in words.
+pub fn turns(rotation: usize) -> String {
+    match rotation {
+        0 => "no rotation".to_string(),
+        1 => "1 turn clockwise".to_string(),
+        n => format!("{n} turns clockwise"),
+    }
+}
+
+/// Same as `to_instructions`, keeping the order of `placements`.
+pub fn to_steps(placements: &[&Placement]) -> String {
+    let lines: Vec<String> = placements.iter().enumerate().map(|(idx, placement)| {
+        let turns = turns(placement.rotation);
+        let (row, col) = top_left(placement);
+        format!("{:>2}. {}  {turns:<19}row {row}, column {col}", idx + 1, placement.piece)
+    }).collect();
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_padded_pieces;
+
+    #[test]
+    fn box_drawing() {
+        let pieces = get_padded_pieces((2, 4));
+        let i_piece = &pieces[1].get(&(0, 0)).unwrap()[0];
+        let o_left = &pieces[6].get(&(0, 0)).unwrap()[0];
+        let o_right = &pieces[6].get(&(0, 2)).unwrap()[0];
+        let i_bottom = &pieces[1].get(&(1, 0)).unwrap()[0];
+
+        assert_eq!(to_box_drawing((2, 4), &[o_left, o_right]),
+                   "┌───────┬───────┐\n\
+                    │ O   O │ O   O │\n\
+                    │       │       │\n\
+                    │ O   O │ O   O │\n\
+                    └───────┴───────┘");
+        assert_eq!(to_box_drawing((2, 4), &[i_piece, i_bottom]),
+                   "┌───────────────┐\n\
+                    │ I   I   I   I │\n\
+                    ├───────────────┤\n\
+                    │ I   I   I   I │\n\
+                    └───────────────┘");
+    }
+
+    #[test]
+    fn neighbours_get_distinct_colors() {
+        let board = Bitmap2D::zeros((5, 8));
+        let pieces = get_padded_pieces(board.shape);
+        let solution = crate::solver::fill_board(&board, [2, 4, 2, 0, 1, 1, 0], (0, 0), &pieces).unwrap();
+        let neighbours = adjacency(board.shape, &solution);
+        assert!(neighbours.iter().any(|n| !n.is_empty()));
+        // The talos and colorblind palettes have 8 colors.
+        for palette_size in [PALETTE_SIZE, 8, 4] {
+            let (colors, distinct) = color_pieces(board.shape, &solution, palette_size);
+            assert!(distinct);
+            for (idx, list) in neighbours.iter().enumerate() {
+                for &other in list {
+                    assert_ne!(colors[idx], colors[other]);
+                }
+            }
+            assert!(colors.iter().all(|&c| c < palette_size));
+        }
+
+        // Two neighbours cannot be told apart with a single color.
+        let pieces = get_padded_pieces((2, 4));
+        let o_left = &pieces[6].get(&(0, 0)).unwrap()[0];
+        let o_right = &pieces[6].get(&(0, 2)).unwrap()[0];
+        assert_eq!(color_pieces((2, 4), &[o_left, o_right], 1), (vec![0, 0], false));
+    }
+
+    #[test]
+    fn colored_grid() {
+        let pieces = get_padded_pieces((2, 4));
+        let o_left = &pieces[6].get(&(0, 0)).unwrap()[0];
+        let o_right = &pieces[6].get(&(0, 2)).unwrap()[0];
+        let placements = [o_left, o_right];
+        assert_eq!(adjacency((2, 4), &placements), vec![vec![1], vec![0]]);
+        let (colors, _) = color_pieces((2, 4), &placements, PALETTE_SIZE);
+        assert_eq!(colors, vec![0, 1]);
+        assert_eq!(color_grid((2, 4), &placements, &colors), "1122\n1122");
+        assert_eq!(color_grid((2, 4), &placements[..1], &[0]), "1100\n1100");
+    }
+
+    #[test]
+    fn colors_by_piece_type() {
+        let pieces = get_padded_pieces((2, 4));
+        let o_left = &pieces[6].get(&(0, 0)).unwrap()[0];
+        let o_right = &pieces[6].get(&(0, 2)).unwrap()[0];
+        let i_piece = &pieces[1].get(&(0, 0)).unwrap()[0];
+        assert_eq!(piece_type_colors(&[o_left, o_right, i_piece], 15), vec![6, 6, 1]);
+        assert_eq!(piece_type_colors(&[o_left], 4), vec![2]);
+    }
+
+    #[test]
+    fn instructions() {
+        let pieces = get_padded_pieces((3, 4));
+        let j_piece = pieces[0].get(&(0, 0)).unwrap().iter().find(|p| p.variant == 2).unwrap();
+        let t_piece = pieces[3].get(&(0, 3)).unwrap().iter().find(|p| p.variant == 0).unwrap();
+        let i_piece = &pieces[1].get(&(2, 0)).unwrap()[0];
+        assert_eq!(top_left(t_piece), (0, 2));
+        assert_eq!(to_instructions(&[i_piece, t_piece, j_piece]),
+                   " 1. J  no rotation        row 0, column 0\n\
+                    \x202. T  3 turns clockwise  row 0, column 2\n\
+                    \x203. I  no rotation        row 2, column 0");
+    }
+
+    #[test]
+    fn box_drawing_empty_cells() {
+        let pieces = get_padded_pieces((2, 4));
+        let i_piece = &pieces[1].get(&(0, 0)).unwrap()[0];
+        assert_eq!(to_box_drawing((2, 4), &[i_piece]),
+                   "┌───────────────┐\n\
+                    │ I   I   I   I │\n\
+                    ├───────────────┤\n\
+                    │               │\n\
+                    └───────────────┘");
+    }
+}
@@ -0,0 +1,131 @@
+use crate::piece::{PaddedPieces, Placement, PIECE_ORDER};
+use std::collections::{BTreeSet, HashMap};
+
+/// Cells connected to `start` with the same letter, in row-major order.
+fn region(grid: &[Vec<char>], start: (usize, usize), seen: &mut [Vec<bool>]) -> Vec<(usize, usize)> {
+    let letter = grid[start.0][start.1];
+    let mut cells = Vec::new();
+    let mut stack = vec![start];
+    seen[start.0][start.1] = true;
+    while let Some((row, col)) = stack.pop() {
+        cells.push((row, col));
+        let neighbours = [(row.wrapping_sub(1), col), (row + 1, col),
+                          (row, col.wrapping_sub(1)), (row, col + 1)];
+        for (row, col) in neighbours {
+            if grid.get(row).and_then(|line| line.get(col)) == Some(&letter) && !seen[row][col] {
+                seen[row][col] = true;
+                stack.push((row, col));
+            }
+        }
+    }
+    cells.sort();
+    cells
+}
+
+/// The placement covering exactly `cells`, sorted in row-major order.
+pub fn identify<'a>(tables: &'a PaddedPieces, cells: &[(usize, usize)]) -> Option<&'a Placement> {
+    tables.iter()
+        .filter_map(|table| table.get(cells.first()?))
+        .flatten()
+        .find(|placement| placement.bitmap.ones() == cells)
+}
+
+/// Covers `left` with placements from `table`, always covering the first
+/// cell left like `fill_board` does.
+fn split<'a>(table: &'a HashMap<(usize, usize), Vec<Placement>>, left: &mut BTreeSet<(usize, usize)>,
+             pieces: &mut Vec<&'a Placement>) -> bool {
+    let Some(&first) = left.first() else { return true };
+    for placement in table.get(&first).into_iter().flatten() {
+        let cells = placement.bitmap.ones();
+        if !cells.iter().all(|cell| left.contains(cell)) { continue }
+        for cell in &cells {
+            left.remove(cell);
+        }
+        pieces.push(placement);
+        if split(table, left, pieces) { return true }
+        pieces.pop();
+        left.extend(cells);
+    }
+    false
+}
+
+/// Splits a grid of piece letters into pieces: each group of connected
+/// cells with the same letter has to be made of pieces of that type. Returns
+/// the pieces found and one error per group that is not. '.' cells are
+/// reported as not covered. Positions in errors count from 1. `tables` are
+/// the placement tables of the grid size.
+pub fn segment<'a>(grid: &[Vec<char>], tables: &'a PaddedPieces) -> (Vec<&'a Placement>, Vec<String>) {
+    let mut seen: Vec<Vec<bool>> = grid.iter().map(|row| vec![false; row.len()]).collect();
+    let mut pieces = Vec::new();
+    let mut errors = Vec::new();
+    for (row, line) in grid.iter().enumerate() {
+        for (col, &letter) in line.iter().enumerate() {
+            if seen[row][col] { continue }
+            let cells = region(grid, (row, col), &mut seen);
+            let at = format!("row {}, column {}", row + 1, col + 1);
+            if letter == '.' {
+                errors.push(format!("Cell at {at} is not covered"));
+                continue;
+            }
+            if !cells.len().is_multiple_of(4) {
+                errors.push(format!("Wrong shape at {at}: {} connected {letter} cell(s), \
+                                     not a multiple of 4", cells.len()));
+                continue;
+            }
+            if cells.len() == 4 {
+                match identify(tables, &cells) {
+                    Some(placement) if placement.piece == letter => pieces.push(placement),
+                    Some(placement) => errors.push(format!(
+                        "Wrong shape at {at}: these {letter} cells are shaped like {}", placement.piece)),
+                    None => errors.push(format!(
+                        "Wrong shape at {at}: these {letter} cells are not a tetromino")),
+                }
+                continue;
+            }
+            let table = PIECE_ORDER.iter().position(|&piece| piece == letter)
+                .map(|piece_id| &tables[piece_id]);
+            let mut left = cells.iter().copied().collect();
+            let mut split_pieces = Vec::new();
+            match table {
+                Some(table) if split(table, &mut left, &mut split_pieces) =>
+                    pieces.extend(split_pieces),
+                _ => errors.push(format!("Wrong shape at {at}: {} connected {letter} cells \
+                                          cannot be split into {letter} pieces", cells.len())),
+            }
+        }
+    }
+    (pieces, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_padded_pieces;
+
+    fn grid(rows: &[&str]) -> Vec<Vec<char>> {
+        rows.iter().map(|row| row.chars().collect()).collect()
+    }
+
+    #[test]
+    fn segments() {
+        // Two Is side by side and two Os in a row touch each other.
+        let tables = get_padded_pieces((4, 4));
+        let (pieces, errors) = segment(&grid(&["IIII", "IIII", "OOOO", "OOOO"]), &tables);
+        assert!(errors.is_empty());
+        let found: Vec<(char, usize, (usize, usize))> = pieces.iter()
+            .map(|p| (p.piece, p.rotation, p.origin))
+            .collect();
+        assert_eq!(found, [('I', 0, (0, 0)), ('I', 0, (1, 0)), ('O', 0, (2, 0)), ('O', 0, (2, 2))]);
+
+        let tables = get_padded_pieces((3, 4));
+        let (pieces, errors) = segment(&grid(&["OOJT", "OOJT", ".JJT"]), &tables);
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(errors, ["Wrong shape at row 1, column 4: 3 connected T cell(s), not a multiple of 4",
+                            "Cell at row 3, column 1 is not covered"]);
+
+        let (_, errors) = segment(&grid(&["JJJJ", "SSTT", "SSTT"]), &tables);
+        assert_eq!(errors, ["Wrong shape at row 1, column 1: these J cells are shaped like I",
+                            "Wrong shape at row 2, column 1: these S cells are shaped like O",
+                            "Wrong shape at row 2, column 3: these T cells are shaped like O"]);
+    }
+}
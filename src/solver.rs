@@ -0,0 +1,259 @@
+use crate::bitmap::Bitmap2D;
+use crate::piece::{Placement, PaddedPieces};
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
+
+pub fn increment(shape: (usize, usize), index: (usize, usize)) -> Option<(usize, usize)> {
+    let flat_index = index.0*shape.1 + index.1 + 1;
+    if shape.1 == 0 { return None }
+    let new_index = (flat_index / shape.1,
+                     flat_index % shape.1);
+    if new_index.0 >= shape.0 { None } else { Some(new_index) }
+}
+
+pub fn fill_board<'a>(board: &Bitmap2D, remaining_pieces: [u32; 7],
+              position: (usize, usize),
+              padded_pieces: &'a PaddedPieces)
+                -> Option<Vec<&'a Placement>> {
+    let next_pos = match increment(board.shape, position) {
+        Some(coord) => coord,
+        None => return Some(vec![]) // not necessarily true ?
+    };
+    if board.get(position).unwrap_or(false)  {
+        return fill_board(board, remaining_pieces, next_pos, padded_pieces);
+    }
+    for (piece_id, piece_dict) in padded_pieces.iter().enumerate() {
+        if remaining_pieces[piece_id] == 0 { continue }
+        if let Some(variants) = piece_dict.get(&position) {
+            for variant in variants {
+                if !board.intersects(&variant.bitmap) {
+                    let new_board = board.or(&variant.bitmap);
+                    let mut new_remaining = remaining_pieces;
+                    new_remaining[piece_id] -= 1;
+                    if let Some(mut solution) = fill_board(&new_board, new_remaining,
+                                                       next_pos, padded_pieces) {
+                        solution.push(variant);
+                        return Some(solution);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+pub fn all_solutions<'a>(board: &Bitmap2D, remaining_pieces: [u32; 7],
+              position: (usize, usize),
+              padded_pieces: &'a PaddedPieces)
+                -> Vec<Vec<&'a Placement>> {
+    let next_pos = match increment(board.shape, position) {
+        Some(coord) => coord,
+        None => return vec![Vec::new()]
+    };
+    if board.get(position).unwrap_or(false)  {
+        return all_solutions(board, remaining_pieces, next_pos, padded_pieces);
+    }
+    let mut solutions = Vec::new();
+    for (piece_id, piece_dict) in padded_pieces.iter().enumerate() {
+        if remaining_pieces[piece_id] == 0 { continue }
+        if let Some(variants) = piece_dict.get(&position) {
+            for variant in variants {
+                if !board.intersects(&variant.bitmap) {
+                    //println!("\n{}",
+                    //         to_ansi(Bitmap2D::print_all(vec![board, variant].into_iter())));
+                    let new_board = board.or(&variant.bitmap);
+                    let mut new_remaining = remaining_pieces;
+                    new_remaining[piece_id] -= 1;
+                    for mut solution in all_solutions(&new_board, new_remaining, next_pos,
+                                                  padded_pieces).into_iter() {
+                        solution.push(variant);
+                        solutions.push(solution);
+                    }
+                }
+            }
+        }
+    }
+    solutions
+}
+
+/// Number of nodes between two deadline checks.
+pub const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+/// A search went past its deadline.
+#[derive(Debug)]
+pub struct TimedOut;
+
+/// Same search as `fill_board` and `all_solutions`, with a node counter, an
+/// optional deadline, and solutions handed to a visitor in placement order
+/// as soon as they are found.
+pub struct Solver<'a> {
+    padded_pieces: &'a PaddedPieces,
+    deadline: Option<Instant>,
+    /// Number of pieces placed so far, across every call.
+    pub nodes: u64,
+    pub timed_out: bool,
+}
+
+impl<'a> Solver<'a> {
+    pub fn new(padded_pieces: &'a PaddedPieces) -> Self {
+        Self { padded_pieces, deadline: None, nodes: 0, timed_out: false }
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.deadline = timeout.map(|t| Instant::now() + t);
+        self
+    }
+
+    pub fn first(&mut self, board: &Bitmap2D, remaining_pieces: [u32; 7])
+                   -> Option<Vec<&'a Placement>> {
+        let mut solution = None;
+        self.for_each(board, remaining_pieces, |sol| {
+            solution = Some(sol.to_vec());
+            ControlFlow::Break(())
+        });
+        solution
+    }
+
+    pub fn count(&mut self, board: &Bitmap2D, remaining_pieces: [u32; 7]) -> usize {
+        let mut count = 0;
+        self.for_each(board, remaining_pieces, |_| {
+            count += 1;
+            ControlFlow::Continue(())
+        });
+        count
+    }
+
+    /// Calls `visit` on every solution until it breaks or the deadline passes.
+    pub fn for_each(&mut self, board: &Bitmap2D, remaining_pieces: [u32; 7],
+                    mut visit: impl FnMut(&[&'a Placement]) -> ControlFlow<()>) {
+        let _ = self.search(board, remaining_pieces, Some((0, 0)), &mut Vec::new(), &mut visit);
+    }
+
+    fn search(&mut self, board: &Bitmap2D, remaining_pieces: [u32; 7],
+              position: Option<(usize, usize)>, placed: &mut Vec<&'a Placement>,
+              visit: &mut impl FnMut(&[&'a Placement]) -> ControlFlow<()>) -> ControlFlow<()> {
+        let Some(position) = position else { return visit(placed) };
+        let next_pos = increment(board.shape, position);
+        if board.get(position).unwrap_or(false) {
+            return self.search(board, remaining_pieces, next_pos, placed, visit);
+        }
+        for (piece_id, piece_dict) in self.padded_pieces.iter().enumerate() {
+            if remaining_pieces[piece_id] == 0 { continue }
+            let Some(variants) = piece_dict.get(&position) else { continue };
+            for variant in variants {
+                if board.intersects(&variant.bitmap) { continue }
+                self.nodes += 1;
+                if self.nodes.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                    && self.deadline.is_some_and(|d| Instant::now() >= d) {
+                    self.timed_out = true;
+                    return ControlFlow::Break(());
+                }
+                let new_board = board.or(&variant.bitmap);
+                let mut new_remaining = remaining_pieces;
+                new_remaining[piece_id] -= 1;
+                placed.push(variant);
+                let flow = self.search(&new_board, new_remaining, next_pos, placed, visit);
+                placed.pop();
+                flow?;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+// TODO Use multithreading to parallelize the search
+// Manage cases where n_pieces != 4*H*W
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_padded_pieces;
+
+    #[test]
+    fn test_increment() {
+        assert_eq!(increment((4, 4), (2, 2)), Some((2,3)));
+        assert_eq!(increment((4, 4), (2, 3)), Some((3,0)));
+        assert_eq!(increment((4, 4), (3, 3)), None);
+        assert_eq!(increment((0, 0), (3, 3)), None);
+    }
+
+    #[test]
+    fn i_o_pieces() {
+        let board_size = (4, 4);
+        let pieces = get_padded_pieces(board_size);
+        let board = Bitmap2D::zeros(board_size);
+
+        let piece_count: [u32; 7] = [0, 4, 0, 0, 0, 0, 0];
+        let solution = fill_board(&board, piece_count, (0,0), &pieces);
+        assert!(solution.is_some());
+
+        let piece_count: [u32; 7] = [0, 2, 0, 0, 0, 0, 2];
+        let solution = fill_board(&board, piece_count, (0,0), &pieces);
+        assert!(solution.is_some());
+
+        let piece_count: [u32; 7] = [0, 3, 0, 0, 0, 0, 1];
+        let solution = fill_board(&board, piece_count, (0,0), &pieces);
+        assert!(solution.is_none());
+
+        let board = Bitmap2D::zeros((18,4));
+        let pieces = get_padded_pieces(board.shape);
+        let piece_count: [u32; 7] = [0, 18, 0, 0, 0, 0, 0];
+        assert!(fill_board(&board, piece_count, (0,0), &pieces).is_some());
+        
+        let board = Bitmap2D::zeros((17,4));
+        let pieces = get_padded_pieces(board.shape);
+        let piece_count: [u32; 7] = [0, 0, 0, 0, 0, 0, 17];
+        assert!(fill_board(&board, piece_count, (0,0), &pieces).is_none());
+    }
+
+    #[test]
+    fn num_solutions() {
+        let board = Bitmap2D::zeros((4,4));
+        let pieces = get_padded_pieces(board.shape);
+
+        let piece_count: [u32; 7] = [0, 0, 0, 0, 0, 0, 4];
+        let solutions = all_solutions(&board, piece_count, (0,0), &pieces);
+        assert_eq!(solutions.len(), 1);
+
+        let piece_count: [u32; 7] = [0, 4, 0, 0, 0, 0, 0];
+        let solutions = all_solutions(&board, piece_count, (0,0), &pieces);
+        assert_eq!(solutions.len(), 2);
+
+        let piece_count: [u32; 7] = [0, 2, 0, 0, 0, 0, 2];
+        let solutions = all_solutions(&board, piece_count, (0,0), &pieces);
+        assert_eq!(solutions.len(), 6);
+    }
+
+    #[test]
+    fn solver_matches_reference() {
+        let board = Bitmap2D::zeros((4,4));
+        let pieces = get_padded_pieces(board.shape);
+
+        let piece_count: [u32; 7] = [0, 2, 0, 0, 0, 0, 2];
+        let mut streamed = Vec::new();
+        Solver::new(&pieces).for_each(&board, piece_count, |sol| {
+            streamed.push(sol.len());
+            ControlFlow::Continue(())
+        });
+        assert_eq!(streamed, vec![4; 6]);
+        assert_eq!(Solver::new(&pieces).count(&board, piece_count), 6);
+
+        let mut solver = Solver::new(&pieces);
+        let mut first = solver.first(&board, piece_count).unwrap();
+        first.reverse();
+        let reference = fill_board(&board, piece_count, (0,0), &pieces).unwrap();
+        assert!(first.iter().zip(&reference).all(|(a, b)| std::ptr::eq(*a, *b)));
+        assert!(solver.nodes >= 4);
+        assert!(Solver::new(&pieces).first(&board, [0, 3, 0, 0, 0, 0, 1]).is_none());
+    }
+
+    #[test]
+    fn timeout() {
+        let board = Bitmap2D::zeros((8, 8));
+        let pieces = get_padded_pieces(board.shape);
+        let mut solver = Solver::new(&pieces).with_timeout(Some(Duration::ZERO));
+        solver.count(&board, [4, 4, 4, 0, 0, 0, 4]);
+        assert!(solver.timed_out);
+        assert_eq!(solver.nodes, DEADLINE_CHECK_INTERVAL);
+    }
+}
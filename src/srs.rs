@@ -0,0 +1,176 @@
+use crate::bitmap::Bitmap2D;
+use crate::piece::Placement;
+use std::collections::{HashSet, VecDeque};
+
+/// Cells of each piece in spawn orientation inside its SRS bounding box,
+/// with the size of that box.
+fn spawn_cells(piece: char) -> (usize, &'static [(usize, usize)]) {
+    match piece {
+        'I' => (4, &[(1, 0), (1, 1), (1, 2), (1, 3)]),
+        'O' => (2, &[(0, 0), (0, 1), (1, 0), (1, 1)]),
+        'T' => (3, &[(0, 1), (1, 0), (1, 1), (1, 2)]),
+        'J' => (3, &[(0, 0), (1, 0), (1, 1), (1, 2)]),
+        'L' => (3, &[(0, 2), (1, 0), (1, 1), (1, 2)]),
+        'S' => (3, &[(0, 1), (0, 2), (1, 0), (1, 1)]),
+        'Z' => (3, &[(0, 0), (0, 1), (1, 1), (1, 2)]),
+        _ => panic!("Unknown piece {piece}"),
+    }
+}
+
+/// Cells of `piece` in its box after `rotation` clockwise quarter turns.
+fn rotated_cells(piece: char, rotation: usize) -> Vec<(isize, isize)> {
+    let (size, cells) = spawn_cells(piece);
+    cells.iter().map(|&(row, col)| {
+        let (mut row, mut col) = (row as isize, col as isize);
+        for _ in 0..rotation {
+            (row, col) = (col, size as isize - 1 - row);
+        }
+        (row, col)
+    }).collect()
+}
+
+/// Offsets to try, in order, when rotating clockwise from `from`, as
+/// (right, up) like in the SRS tables. Counter-clockwise rotations use the
+/// opposite of the clockwise rotation ending at `from`.
+const JLSTZ_KICKS: [[(isize, isize); 5]; 4] = [
+    [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)],
+    [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)],
+    [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)],
+    [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)],
+];
+
+const I_KICKS: [[(isize, isize); 5]; 4] = [
+    [(0, 0), (-2, 0), (1, 0), (-2, -1), (1, 2)],
+    [(0, 0), (-1, 0), (2, 0), (-1, 2), (2, -1)],
+    [(0, 0), (2, 0), (-1, 0), (2, 1), (-1, -2)],
+    [(0, 0), (1, 0), (-2, 0), (1, -2), (-2, 1)],
+];
+
+fn kicks(piece: char, from: usize, clockwise: bool) -> Vec<(isize, isize)> {
+    let table = match piece {
+        'O' => return vec![(0, 0)],
+        'I' => &I_KICKS,
+        _ => &JLSTZ_KICKS,
+    };
+    if clockwise {
+        table[from].to_vec()
+    } else {
+        table[(from + 3) % 4].iter().map(|&(right, up)| (-right, -up)).collect()
+    }
+}
+
+/// A piece in its box at `position`, the top-left corner of the box.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    rotation: usize,
+    position: (isize, isize),
+}
+
+/// Whether `placement` can be reached from the spawn position above the
+/// board with shifts, soft drops and SRS rotations including wall kicks,
+/// and rests there.
+pub fn can_reach(placement: &Placement, board: &Bitmap2D) -> bool {
+    let (rows, cols) = board.shape;
+    let piece = placement.piece;
+    let shapes: Vec<Vec<(isize, isize)>> = (0..4).map(|r| rotated_cells(piece, r)).collect();
+    let cells_at = |state: State| shapes[state.rotation].iter()
+        .map(move |&(row, col)| (row + state.position.0, col + state.position.1));
+    let free = |state: State| cells_at(state).all(|(row, col)| {
+        col >= 0 && col < cols as isize && row < rows as isize
+            && (row < 0 || !board.get((row as usize, col as usize)).unwrap_or(true))
+    });
+    let mut target: Vec<(isize, isize)> = placement.bitmap.ones().into_iter()
+        .map(|(row, col)| (row as isize, col as isize))
+        .collect();
+    target.sort();
+    let is_target = |state: State| {
+        let mut cells: Vec<(isize, isize)> = cells_at(state).collect();
+        cells.sort();
+        cells == target
+    };
+
+    let (size, _) = spawn_cells(piece);
+    let spawn = State { rotation: 0,
+                        position: (-(size as isize), (cols as isize - size as isize) / 2) };
+    let mut seen = HashSet::from([spawn]);
+    let mut queue = VecDeque::from([spawn]);
+    while let Some(state) = queue.pop_front() {
+        let (row, col) = state.position;
+        let down = State { position: (row + 1, col), ..state };
+        if is_target(state) && !free(down) { return true }
+        let mut next = vec![down,
+                            State { position: (row, col - 1), ..state },
+                            State { position: (row, col + 1), ..state }];
+        for clockwise in [true, false] {
+            let rotation = if clockwise { (state.rotation + 1) % 4 } else { (state.rotation + 3) % 4 };
+            let kicked = kicks(piece, state.rotation, clockwise).into_iter()
+                .map(|(right, up)| State { rotation, position: (row - up, col + right) })
+                .find(|&kicked| free(kicked));
+            next.extend(kicked);
+        }
+        for state in next {
+            if state.position.0 <= rows as isize && free(state) && seen.insert(state) {
+                queue.push_back(state);
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_padded_pieces;
+
+    fn field(rows: &[&str]) -> Bitmap2D {
+        let mut board = Bitmap2D::zeros((rows.len(), rows[0].len()));
+        for (row, line) in rows.iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                if c == '#' { board.set((row, col)) }
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn shapes_match_variants() {
+        let board = Bitmap2D::zeros((4, 4));
+        let pieces = get_padded_pieces(board.shape);
+        for table in &pieces {
+            for placement in table.values().flatten() {
+                let cells = rotated_cells(placement.piece, placement.rotation);
+                let top = cells.iter().map(|c| c.0).min().unwrap();
+                let left = cells.iter().map(|c| c.1).min().unwrap();
+                let mut cells: Vec<(usize, usize)> = cells.iter()
+                    .map(|&(row, col)| ((row - top) as usize, (col - left) as usize))
+                    .collect();
+                cells.sort();
+                let (top, left) = crate::render::top_left(placement);
+                let expected: Vec<(usize, usize)> = placement.bitmap.ones().iter()
+                    .map(|&(row, col)| (row - top, col - left))
+                    .collect();
+                assert_eq!(cells, expected, "{} {}", placement.piece, placement.variant);
+            }
+        }
+    }
+
+    #[test]
+    fn t_spin() {
+        // T-spin double slot: the T has to rotate into it from above.
+        let board = field(&["..........",
+                            "..........",
+                            "##........",
+                            "#...######",
+                            "##.#######"]);
+        let pieces = get_padded_pieces(board.shape);
+        let t_down = pieces[3].get(&(3, 1)).unwrap().iter().find(|p| p.rotation == 2).unwrap();
+        assert!(can_reach(t_down, &board));
+        assert!(!crate::gravity::can_drop(t_down, &board, crate::gravity::DropMode::Shift));
+
+        // Floating pieces do not rest.
+        let i_flat = &pieces[1].get(&(0, 0)).unwrap()[0];
+        assert!(!can_reach(i_flat, &board));
+        let i_bottom = pieces[1].get(&(2, 4)).unwrap().iter().find(|p| p.rotation == 0).unwrap();
+        assert!(can_reach(i_bottom, &board));
+    }
+}
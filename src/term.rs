@@ -0,0 +1,225 @@
+use std::env;
+use std::io::IsTerminal;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorSupport {
+    NoColor,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl std::str::FromStr for ColorSupport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(ColorSupport::NoColor),
+            "16" => Ok(ColorSupport::Ansi16),
+            "256" => Ok(ColorSupport::Ansi256),
+            "truecolor" => Ok(ColorSupport::TrueColor),
+            _ => Err(format!("Unrecognized color mode: {s} (expected auto, never, 16, 256 or truecolor)"))
+        }
+    }
+}
+
+/// Picks the richest color mode the environment allows, following the
+/// `NO_COLOR` convention and turning colors off when stdout is not a tty.
+pub fn detect_color_support() -> ColorSupport {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    color_support_from(no_color, std::io::stdout().is_terminal(),
+                       env::var("TERM").ok().as_deref(),
+                       env::var("COLORTERM").ok().as_deref())
+}
+
+fn color_support_from(no_color: bool, is_tty: bool, term: Option<&str>,
+                      colorterm: Option<&str>) -> ColorSupport {
+    if no_color || !is_tty || term == Some("dumb") {
+        return ColorSupport::NoColor;
+    }
+    if matches!(colorterm, Some("truecolor" | "24bit"))
+        || term.is_some_and(|t| t.contains("truecolor") || t.contains("direct")) {
+        return ColorSupport::TrueColor;
+    }
+    if term.is_some_and(|t| t.contains("256color")) {
+        return ColorSupport::Ansi256;
+    }
+    ColorSupport::Ansi16
+}
+
+pub struct Swatch {
+    /// Exact color, or `None` to keep the terminal's own 16-color palette.
+    pub rgb: Option<(u8, u8, u8)>,
+    /// Closest of the 16 standard ANSI colors.
+    pub ansi16: u8,
+    /// Two characters drawn in the cell; blank means a plain color block.
+    pub fill: &'static str,
+}
+
+const fn term_color(ansi16: u8) -> Swatch {
+    Swatch { rgb: None, ansi16, fill: "  " }
+}
+
+const fn rgb_color(rgb: (u8, u8, u8), ansi16: u8, fill: &'static str) -> Swatch {
+    Swatch { rgb: Some(rgb), ansi16, fill }
+}
+
+const DEFAULT_SWATCHES: [Swatch; 15] = [
+    term_color(1), term_color(2), term_color(3), term_color(4), term_color(5),
+    term_color(6), term_color(7), term_color(8), term_color(9), term_color(10),
+    term_color(11), term_color(12), term_color(13), term_color(14), term_color(15),
+];
+
+// Tones of the sigils and stonework of The Talos Principle.
+const TALOS_SWATCHES: [Swatch; 8] = [
+    rgb_color((0xd9, 0xa4, 0x1e), 3, "  "),  // tool sigil gold
+    rgb_color((0x3f, 0xa3, 0x4d), 2, "  "),  // door sigil green
+    rgb_color((0xb8, 0x32, 0x27), 1, "  "),  // star sigil red
+    rgb_color((0x4a, 0x7f, 0xb5), 4, "  "),  // Elohim sky
+    rgb_color((0x8c, 0x86, 0x7a), 7, "  "),  // weathered stone
+    rgb_color((0x7e, 0x4a, 0x9c), 5, "  "),  // messenger purple
+    rgb_color((0xe0, 0xd2, 0xa8), 15, "  "), // sandstone
+    rgb_color((0x5c, 0xb8, 0xb2), 6, "  "),  // terminal cyan
+];
+
+// Okabe-Ito palette, paired with distinct fill patterns so that colors
+// never have to be told apart by hue alone.
+const COLORBLIND_SWATCHES: [Swatch; 8] = [
+    rgb_color((0xe6, 0x9f, 0x00), 3, "░░"),
+    rgb_color((0x56, 0xb4, 0xe9), 6, "▒▒"),
+    rgb_color((0x00, 0x9e, 0x73), 2, "▓▓"),
+    rgb_color((0xf0, 0xe4, 0x42), 11, "::"),
+    rgb_color((0x00, 0x72, 0xb2), 4, "//"),
+    rgb_color((0xd5, 0x5e, 0x00), 1, "##"),
+    rgb_color((0xcc, 0x79, 0xa7), 5, "++"),
+    rgb_color((0x99, 0x99, 0x99), 7, ".."),
+];
+
+/// Fill patterns used in place of colors when the terminal has none.
+const NO_COLOR_FILLS: [&str; 15] = [
+    "██", "░░", "▒▒", "▓▓", "##", "::", "//", "++", "..", "%%", "@@", "==", "xx", "oo", "\\\\",
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Palette {
+    Default,
+    Talos,
+    Colorblind,
+}
+
+impl std::str::FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Palette::Default),
+            "talos" => Ok(Palette::Talos),
+            "colorblind" => Ok(Palette::Colorblind),
+            _ => Err(format!("Unrecognized palette: {s} (expected default, talos or colorblind)"))
+        }
+    }
+}
+
+impl Palette {
+    pub fn swatches(&self) -> &'static [Swatch] {
+        match self {
+            Palette::Default => &DEFAULT_SWATCHES,
+            Palette::Talos => &TALOS_SWATCHES,
+            Palette::Colorblind => &COLORBLIND_SWATCHES,
+        }
+    }
+}
+
+fn rgb_to_256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+    16 + 36*level(rgb.0) + 6*level(rgb.1) + level(rgb.2)
+}
+
+fn background(swatch: &Swatch, support: ColorSupport) -> String {
+    match (swatch.rgb, support) {
+        (_, ColorSupport::NoColor) => String::new(),
+        (Some((r, g, b)), ColorSupport::TrueColor) => format!("\x1b[48;2;{r};{g};{b}m"),
+        (Some(rgb), ColorSupport::Ansi256) => format!("\x1b[48;5;{}m", rgb_to_256(rgb)),
+        _ => {
+            let color = swatch.ansi16 as u32;
+            let code = if color < 8 { 40 + color } else { 92 + color };
+            format!("\x1b[{code}m")
+        }
+    }
+}
+
+/// Turns the hex-digit grid produced by `Bitmap2D::print_all` into colored
+/// cells: digit `d` uses swatch `d - 1` of the palette and `0` stays empty.
+pub fn to_ansi(ipt_str: String, support: ColorSupport, palette: Palette) -> String {
+    let swatches = palette.swatches();
+    let cells = ipt_str.chars().map(|x| {
+        let color = match x.to_digit(16) {
+            None => return x.to_string(),
+            Some(col) => col as usize
+        };
+        if color == 0 {
+            return match support {
+                ColorSupport::NoColor => "  ".to_string(),
+                _ => "\x1b[40m  ".to_string()
+            };
+        }
+        let idx = (color - 1) % swatches.len();
+        let swatch = &swatches[idx];
+        let fill = match (support, swatch.fill) {
+            (ColorSupport::NoColor, "  ") => NO_COLOR_FILLS[idx % NO_COLOR_FILLS.len()],
+            (_, fill) => fill,
+        };
+        format!("{}{fill}", background(swatch, support))
+    }).collect::<Vec<String>>().join("");
+    match support {
+        ColorSupport::NoColor => format!("{cells}\n"),
+        _ => format!("{}\x1b[0m\n", cells.replace("\n", "\x1b[0m\n")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detection() {
+        assert_eq!(color_support_from(true, true, Some("xterm-256color"), None),
+                   ColorSupport::NoColor);
+        assert_eq!(color_support_from(false, false, Some("xterm-256color"), None),
+                   ColorSupport::NoColor);
+        assert_eq!(color_support_from(false, true, Some("dumb"), None),
+                   ColorSupport::NoColor);
+        assert_eq!(color_support_from(false, true, Some("xterm-256color"), Some("truecolor")),
+                   ColorSupport::TrueColor);
+        assert_eq!(color_support_from(false, true, Some("xterm-256color"), None),
+                   ColorSupport::Ansi256);
+        assert_eq!(color_support_from(false, true, Some("xterm"), None),
+                   ColorSupport::Ansi16);
+        assert_eq!(color_support_from(false, true, None, None),
+                   ColorSupport::Ansi16);
+    }
+
+    #[test]
+    fn ansi16_default_palette() {
+        assert_eq!(to_ansi("19\n0f".to_string(), ColorSupport::Ansi16, Palette::Default),
+                   "\x1b[41m  \x1b[101m  \x1b[0m\n\x1b[40m  \x1b[107m  \x1b[0m\n");
+    }
+
+    #[test]
+    fn rgb_palettes() {
+        assert_eq!(to_ansi("1".to_string(), ColorSupport::TrueColor, Palette::Talos),
+                   "\x1b[48;2;217;164;30m  \x1b[0m\n");
+        assert_eq!(to_ansi("1".to_string(), ColorSupport::Ansi256, Palette::Colorblind),
+                   "\x1b[48;5;214m░░\x1b[0m\n");
+        assert_eq!(rgb_to_256((0, 0, 0)), 16);
+        assert_eq!(rgb_to_256((255, 255, 255)), 231);
+    }
+
+    #[test]
+    fn no_color_fills() {
+        assert_eq!(to_ansi("12\n30".to_string(), ColorSupport::NoColor, Palette::Default),
+                   "██░░\n▒▒  \n");
+        assert_eq!(to_ansi("12".to_string(), ColorSupport::NoColor, Palette::Colorblind),
+                   "░░▒▒\n");
+    }
+}
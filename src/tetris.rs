@@ -0,0 +1,205 @@
+use crate::bitmap::Bitmap2D;
+use crate::gravity::{can_drop, DropMode};
+use crate::piece::{Placement, PaddedPieces, PIECE_ORDER};
+use crate::solver::DEADLINE_CHECK_INTERVAL;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// Width of a Tetris playfield.
+pub const WIDTH: usize = 10;
+
+/// One piece dropped during a perfect clear, in the coordinates of the
+/// field at that time.
+pub struct Step<'a> {
+    pub placement: &'a Placement,
+    pub from_hold: bool,
+    /// Lines cleared by this piece.
+    pub cleared: usize,
+}
+
+/// Removes full rows, shifting the rows above them down.
+pub fn clear_lines(board: &Bitmap2D) -> (Bitmap2D, usize) {
+    let (rows, cols) = board.shape;
+    let kept: Vec<usize> = (0..rows)
+        .filter(|&row| (0..cols).any(|col| !board.get((row, col)).unwrap_or(false)))
+        .collect();
+    let cleared = rows - kept.len();
+    if cleared == 0 {
+        return (Bitmap2D { shape: board.shape, data: board.data.clone() }, 0);
+    }
+    let mut out = Bitmap2D::zeros(board.shape);
+    for (new_row, &row) in kept.iter().enumerate() {
+        for col in 0..cols {
+            if board.get((row, col)).unwrap_or(false) {
+                out.set((new_row + cleared, col));
+            }
+        }
+    }
+    (out, cleared)
+}
+
+/// The next piece to play: either the current one or, with hold, the held
+/// piece (or the one after the current piece when nothing is held yet).
+pub struct Choice {
+    pub piece: char,
+    /// Position in the queue after playing `piece`.
+    pub next: usize,
+    pub hold: Option<char>,
+    pub from_hold: bool,
+}
+
+pub fn choices(queue: &[char], next: usize, hold: Option<char>, use_hold: bool) -> Vec<Choice> {
+    let mut out = Vec::new();
+    if let Some(&piece) = queue.get(next) {
+        out.push(Choice { piece, next: next + 1, hold, from_hold: false });
+    }
+    if !use_hold { return out }
+    match (hold, queue.get(next)) {
+        (Some(held), Some(&current)) if held != current =>
+            out.push(Choice { piece: held, next: next + 1, hold: Some(current), from_hold: true }),
+        (Some(held), None) =>
+            out.push(Choice { piece: held, next, hold: None, from_hold: true }),
+        (None, Some(&current)) => if let Some(&piece) = queue.get(next + 1) {
+            out.push(Choice { piece, next: next + 2, hold: Some(current), from_hold: true });
+        },
+        _ => {},
+    }
+    out
+}
+
+/// Depth-first search for a sequence of drops emptying the field, with a
+/// memo of the fields, queue positions and held pieces that lead nowhere.
+pub struct PerfectClear<'a> {
+    /// Placements of each piece type, lowest first.
+    placements: Vec<Vec<&'a Placement>>,
+    use_hold: bool,
+    mode: DropMode,
+    dead_ends: HashSet<(Vec<u64>, usize, Option<char>)>,
+    deadline: Option<Instant>,
+    /// Number of pieces dropped so far, across every call.
+    pub nodes: u64,
+    pub timed_out: bool,
+}
+
+impl<'a> PerfectClear<'a> {
+    pub fn new(tables: &'a PaddedPieces, use_hold: bool) -> Self {
+        let placements = tables.iter().map(|table| {
+            let mut placements: Vec<&Placement> = table.values().flatten().collect();
+            placements.sort_by_key(|p| {
+                let cells = p.bitmap.ones();
+                (std::cmp::Reverse(cells.iter().map(|c| c.0).max()),
+                 cells.iter().map(|c| c.1).min(), p.variant)
+            });
+            placements
+        }).collect();
+        Self { placements, use_hold, mode: DropMode::Shift, dead_ends: HashSet::new(), deadline: None, nodes: 0,
+               timed_out: false }
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.deadline = timeout.map(|t| Instant::now() + t);
+        self
+    }
+
+    /// How pieces may move into place, shifting only by default.
+    pub fn with_drop_mode(mut self, mode: DropMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Drops pieces from `queue`, in order except for hold, until `board`
+    /// is empty.
+    pub fn find(&mut self, board: &Bitmap2D, queue: &[char], hold: Option<char>)
+                  -> Option<Vec<Step<'a>>> {
+        let mut steps = Vec::new();
+        self.search(board, queue, 0, hold, &mut steps).then_some(steps)
+    }
+
+    fn search(&mut self, board: &Bitmap2D, queue: &[char], next: usize, hold: Option<char>,
+              steps: &mut Vec<Step<'a>>) -> bool {
+        let filled = board.ones();
+        if filled.is_empty() && !steps.is_empty() { return true }
+        // Some number of the pieces left has to complete whole lines,
+        // including every started one.
+        let width = board.shape.1;
+        let started_rows = filled.iter().map(|c| c.0).collect::<HashSet<_>>().len();
+        let pieces_left = queue.len() - next + usize::from(hold.is_some());
+        let completes = |k: usize| {
+            let cells = filled.len() + 4*k;
+            cells.is_multiple_of(width) && cells >= width*started_rows
+        };
+        if !(1..=pieces_left).any(completes) { return false }
+        let key = (board.data.clone(), next, hold);
+        if self.dead_ends.contains(&key) { return false }
+
+        for choice in choices(queue, next, hold, self.use_hold) {
+            let Some(piece_id) = PIECE_ORDER.iter().position(|&c| c == choice.piece) else {
+                continue;
+            };
+            for idx in 0..self.placements[piece_id].len() {
+                let placement = self.placements[piece_id][idx];
+                if board.intersects(&placement.bitmap)
+                    || !can_drop(placement, board, self.mode) { continue }
+                self.nodes += 1;
+                if self.nodes.is_multiple_of(DEADLINE_CHECK_INTERVAL)
+                    && self.deadline.is_some_and(|d| Instant::now() >= d) {
+                    self.timed_out = true;
+                }
+                if self.timed_out { return false }
+                let (new_board, cleared) = clear_lines(&board.or(&placement.bitmap));
+                steps.push(Step { placement, from_hold: choice.from_hold, cleared });
+                if self.search(&new_board, queue, choice.next, choice.hold, steps) {
+                    return true;
+                }
+                steps.pop();
+            }
+        }
+        if !self.timed_out {
+            self.dead_ends.insert(key);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::piece::get_padded_pieces;
+
+    fn field(rows: &[&str]) -> Bitmap2D {
+        let mut board = Bitmap2D::zeros((rows.len(), rows[0].len()));
+        for (row, line) in rows.iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                if c == '#' { board.set((row, col)) }
+            }
+        }
+        board
+    }
+
+    #[test]
+    fn clears() {
+        let (board, cleared) = clear_lines(&field(&["#...", "####", ".#..", "####"]));
+        assert_eq!(cleared, 2);
+        assert_eq!(board.to_string(), "0000\n0000\n1000\n0100");
+    }
+
+    #[test]
+    fn perfect_clears() {
+        // The first T clears the bottom line, the second one the other two.
+        let tables = get_padded_pieces((3, 4));
+        let board = field(&["....", "#...", "###."]);
+        let steps = PerfectClear::new(&tables, false).find(&board, &['T', 'T'], None).unwrap();
+        assert_eq!(steps.iter().map(|s| (s.placement.origin, s.cleared)).collect::<Vec<_>>(),
+                   vec![((0, 3), 1), ((1, 0), 2)]);
+        assert!(PerfectClear::new(&tables, false).find(&board, &['O', 'J'], None).is_none());
+
+        // The O piece cannot fit a single row: hold it and use the I piece.
+        let tables = get_padded_pieces((1, 4));
+        let board = Bitmap2D::zeros((1, 4));
+        assert!(PerfectClear::new(&tables, false).find(&board, &['O', 'I'], None).is_none());
+        let steps = PerfectClear::new(&tables, true).find(&board, &['O', 'I'], None).unwrap();
+        assert_eq!(steps.len(), 1);
+        assert!(steps[0].from_hold);
+        assert_eq!((steps[0].placement.piece, steps[0].cleared), ('I', 1));
+    }
+}